@@ -74,6 +74,7 @@ export enum DownloadableType {
   Tool = "Tool",
   DLC = "DLC",
   Mod = "Mod",
+  Move = "Move",
 }
 
 export type DownloadableMetadata = {
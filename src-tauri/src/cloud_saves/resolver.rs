@@ -1,5 +1,5 @@
 use std::{
-    fs::{self, create_dir_all, File},
+    fs::{self, File, create_dir_all},
     io::{self, ErrorKind, Read, Write},
     path::{Path, PathBuf},
     thread::sleep,
@@ -253,6 +253,7 @@ pub fn test() {
             version_index: 0,
             delta: false,
             umu_id_override: None,
+            compat_tool: None,
         },
         save_id: String::from("aaaaaaa"),
     };
@@ -7,11 +7,14 @@ use std::{
 use serde_json::Value;
 
 use crate::{
-    database::{db::borrow_db_mut_checked, scan::scan_install_dirs}, error::download_manager_error::DownloadManagerError,
+    database::{db::borrow_db_mut_checked, scan::scan_install_dirs},
+    download_manager::util::rate_limiter::DOWNLOAD_RATE_LIMITER,
+    error::download_manager_error::DownloadManagerError,
+    peer::{chunk_server::start_chunk_server, discovery::start_discovery},
 };
 
 use super::{
-    db::{borrow_db_checked, DATA_ROOT_DIR},
+    db::{DATA_ROOT_DIR, borrow_db_checked},
     debug::SystemData,
     models::data::Settings,
 };
@@ -72,7 +75,12 @@ pub fn update_settings(new_settings: Value) {
         current_settings[key] = value.clone();
     }
     let new_settings: Settings = serde_json::from_value(current_settings).unwrap();
+    DOWNLOAD_RATE_LIMITER.set_limit(new_settings.max_download_speed);
     db_lock.settings = new_settings;
+    drop(db_lock);
+
+    start_chunk_server();
+    start_discovery();
 }
 #[tauri::command]
 pub fn fetch_settings() -> Settings {
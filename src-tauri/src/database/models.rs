@@ -1,20 +1,28 @@
 /**
  * NEXT BREAKING CHANGE
- * 
+ *
  * UPDATE DATABASE TO USE RPMSERDENAMED
- * 
+ *
  * WE CAN'T DELETE ANY FIELDS
  */
 pub mod data {
     use std::path::PathBuf;
 
-    
     use native_model::native_model;
     use serde::{Deserialize, Serialize};
 
     pub type GameVersion = v1::GameVersion;
     pub type Database = v3::Database;
     pub type Settings = v1::Settings;
+    pub type RetryPolicy = v1::RetryPolicy;
+    pub type InstalledDlc = v1::InstalledDlc;
+    pub type InstalledMod = v1::InstalledMod;
+    pub type PinnedTool = v1::PinnedTool;
+    pub type QueuedDownload = v1::QueuedDownload;
+    pub type QueuedDownloadKind = v1::QueuedDownloadKind;
+    pub type DownloadHookEvent = v1::DownloadHookEvent;
+    pub type DownloadHookAction = v1::DownloadHookAction;
+    pub type DeployedModFile = v1::DeployedModFile;
     pub type DatabaseAuth = v1::DatabaseAuth;
 
     pub type GameDownloadStatus = v2::GameDownloadStatus;
@@ -37,6 +45,28 @@ pub mod data {
             "{}".to_owned()
         }
 
+        fn default_min_bucket_size() -> usize {
+            8 * 1000 * 1000
+        }
+
+        fn default_max_bucket_size() -> usize {
+            512 * 1000 * 1000
+        }
+
+        fn default_max_concurrent_downloads() -> usize {
+            1
+        }
+
+        fn default_peer_port() -> u16 {
+            46729
+        }
+
+        fn default_stale_file_keep_globs() -> Vec<String> {
+            ["**/saves/**", "**/*.sav", "**/*.cfg", "**/*.ini"]
+                .map(str::to_owned)
+                .to_vec()
+        }
+
         #[derive(Serialize, Deserialize, Clone, Debug)]
         #[serde(rename_all = "camelCase")]
         #[native_model(id = 2, version = 1, with = native_model::rmp_serde_1_3::RmpSerde)]
@@ -62,6 +92,18 @@ pub mod data {
             pub delta: bool,
 
             pub umu_id_override: Option<String>,
+
+            // The runner this version was tested with
+            #[serde(default)]
+            pub compat_tool: Option<PinnedTool>,
+        }
+
+        // A tool download, such as a Proton build, by name and version
+        #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+        #[serde(rename_all = "camelCase")]
+        pub struct PinnedTool {
+            pub name: String,
+            pub version: String,
         }
 
         #[serde_as]
@@ -86,6 +128,36 @@ pub mod data {
             pub autostart: bool,
             pub max_download_threads: usize,
             pub force_offline: bool, // ... other settings ...
+            // In kB/s, 0 is unlimited
+            #[serde(default)]
+            pub max_download_speed: usize,
+            #[serde(default)]
+            pub download_schedule: Option<DownloadSchedule>,
+            // Bounds in bytes for how much a single chunk request asks for
+            #[serde(default = "default_min_bucket_size")]
+            pub min_bucket_size: usize,
+            #[serde(default = "default_max_bucket_size")]
+            pub max_bucket_size: usize,
+            #[serde(default)]
+            pub retry_policy: RetryPolicy,
+            #[serde(default = "default_max_concurrent_downloads")]
+            pub max_concurrent_downloads: usize,
+            // Serve chunks of installed games to other clients on the LAN
+            #[serde(default)]
+            pub share_with_peers: bool,
+            #[serde(default = "default_peer_port")]
+            pub peer_port: u16,
+            // host:port of other clients to try before the server
+            #[serde(default)]
+            pub peers: Vec<String>,
+            #[serde(default)]
+            pub download_hooks: Vec<DownloadHook>,
+            // Files the manifest doesn't list that are left alone, relative to the install directory
+            #[serde(default = "default_stale_file_keep_globs")]
+            pub stale_file_keep_globs: Vec<String>,
+            // Otherwise the user is asked first
+            #[serde(default)]
+            pub remove_stale_files_after_update: bool,
         }
         impl Default for Settings {
             fn default() -> Self {
@@ -93,10 +165,95 @@ pub mod data {
                     autostart: false,
                     max_download_threads: 4,
                     force_offline: false,
+                    max_download_speed: 0,
+                    download_schedule: None,
+                    min_bucket_size: default_min_bucket_size(),
+                    max_bucket_size: default_max_bucket_size(),
+                    retry_policy: RetryPolicy::default(),
+                    max_concurrent_downloads: default_max_concurrent_downloads(),
+                    share_with_peers: false,
+                    peer_port: default_peer_port(),
+                    peers: Vec::new(),
+                    download_hooks: Vec::new(),
+                    stale_file_keep_globs: default_stale_file_keep_globs(),
+                    remove_stale_files_after_update: false,
                 }
             }
         }
 
+        #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+        pub enum DownloadHookEvent {
+            Completed,
+            Error,
+            QueueEmpty,
+        }
+
+        #[derive(Serialize, Deserialize, Clone, Debug)]
+        #[serde(tag = "type")]
+        pub enum DownloadHookAction {
+            // Runs with the download's details in its environment
+            Command { program: String, args: Vec<String> },
+            Notification,
+        }
+
+        #[derive(Serialize, Deserialize, Clone, Debug)]
+        #[serde(rename_all = "camelCase")]
+        pub struct DownloadHook {
+            pub event: DownloadHookEvent,
+            pub action: DownloadHookAction,
+        }
+
+        // Minutes since local midnight. If start is after end, the window wraps past midnight
+        #[derive(Serialize, Deserialize, Clone, Debug)]
+        #[serde(rename_all = "camelCase")]
+        pub struct DownloadSchedule {
+            pub start: u32,
+            pub end: u32,
+        }
+
+        // Backoff is in milliseconds, and doubles with every attempt up to the max
+        #[derive(Serialize, Deserialize, Clone, Debug)]
+        #[serde(rename_all = "camelCase")]
+        pub struct RetryPolicy {
+            pub max_attempts: usize,
+            pub initial_backoff: u64,
+            pub max_backoff: u64,
+        }
+        impl Default for RetryPolicy {
+            fn default() -> Self {
+                Self {
+                    max_attempts: 5,
+                    initial_backoff: 1000,
+                    max_backoff: 60 * 1000,
+                }
+            }
+        }
+
+        // Lives in the parent game's install directory, so moves with it
+        #[derive(Serialize, Deserialize, Clone, Debug)]
+        #[serde(rename_all = "camelCase")]
+        pub struct InstalledDlc {
+            pub version_name: String,
+            pub enabled: bool,
+        }
+
+        #[derive(Serialize, Deserialize, Clone, Debug)]
+        #[serde(rename_all = "camelCase")]
+        pub struct InstalledMod {
+            pub mod_id: String,
+            pub version_name: String,
+            pub enabled: bool,
+        }
+
+        // A file in a game's install directory that a mod put there
+        #[derive(Serialize, Deserialize, Clone, Debug)]
+        #[serde(rename_all = "camelCase")]
+        pub struct DeployedModFile {
+            pub mod_id: String,
+            // Whether the game's original file was moved aside
+            pub backed_up: bool,
+        }
+
         // Strings are version names for a particular game
         #[derive(Serialize, Clone, Deserialize)]
         #[serde(tag = "type")]
@@ -121,6 +278,7 @@ pub mod data {
             Updating { version_name: String },
             Validating { version_name: String },
             Running {},
+            Moving {},
         }
 
         #[derive(serde::Serialize, Clone, Deserialize)]
@@ -141,6 +299,29 @@ pub mod data {
             Tool,
             Dlc,
             Mod,
+            // Moving an installed game, kept apart from downloads of it
+            Move,
+        }
+
+        // What it takes to queue a download again after a restart
+        #[derive(Serialize, Deserialize, Clone, Debug)]
+        pub enum QueuedDownloadKind {
+            Install { base_dir: PathBuf },
+            Update,
+            Repair,
+            Move { base_dir: PathBuf },
+            Dlc { parent_id: String },
+            Mod { parent_id: String },
+            Tool,
+        }
+
+        #[derive(Serialize, Deserialize, Clone, Debug)]
+        #[serde(rename_all = "camelCase")]
+        pub struct QueuedDownload {
+            pub meta: DownloadableMetadata,
+            pub kind: QueuedDownloadKind,
+            #[serde(default)]
+            pub paused: bool,
         }
 
         #[native_model(id = 7, version = 1, with = native_model::rmp_serde_1_3::RmpSerde)]
@@ -180,8 +361,9 @@ pub mod data {
         use serde_with::serde_as;
 
         use super::{
-            ApplicationTransientStatus, DatabaseAuth, Deserialize, DownloadableMetadata,
-            GameVersion, Serialize, Settings, native_model, v1,
+            ApplicationTransientStatus, DatabaseAuth, DeployedModFile, Deserialize,
+            DownloadableMetadata, GameVersion, InstalledDlc, InstalledMod, QueuedDownload,
+            Serialize, Settings, native_model, v1,
         };
 
         #[native_model(id = 1, version = 2, with = native_model::rmp_serde_1_3::RmpSerde)]
@@ -266,12 +448,33 @@ pub mod data {
             pub install_dirs: Vec<PathBuf>,
             // Guaranteed to exist if the game also exists in the app state map
             pub game_statuses: HashMap<String, GameDownloadStatus>,
-            
+
             pub game_versions: HashMap<String, HashMap<String, GameVersion>>,
             pub installed_game_version: HashMap<String, DownloadableMetadata>,
 
             #[serde(skip)]
             pub transient_statuses: HashMap<DownloadableMetadata, ApplicationTransientStatus>,
+
+            // Parent game ID to DLC ID
+            #[serde(default)]
+            pub installed_dlc: HashMap<String, HashMap<String, InstalledDlc>>,
+
+            // Game ID to its mods, in load order
+            #[serde(default)]
+            pub installed_mods: HashMap<String, Vec<InstalledMod>>,
+            // Game ID to the files its enabled mods own, relative to its install directory
+            #[serde(default)]
+            pub deployed_mod_files: HashMap<String, HashMap<PathBuf, DeployedModFile>>,
+
+            // Tool name to its installed versions, oldest first
+            #[serde(default)]
+            pub installed_tools: HashMap<String, Vec<String>>,
+
+            // In queue order
+            #[serde(default)]
+            pub download_queue: Vec<QueuedDownload>,
+            #[serde(default)]
+            pub download_queue_paused: bool,
         }
         impl From<v1::DatabaseApplications> for DatabaseApplications {
             fn from(value: v1::DatabaseApplications) -> Self {
@@ -285,6 +488,12 @@ pub mod data {
                     game_versions: value.game_versions,
                     installed_game_version: value.installed_game_version,
                     transient_statuses: value.transient_statuses,
+                    installed_dlc: HashMap::new(),
+                    installed_mods: HashMap::new(),
+                    deployed_mod_files: HashMap::new(),
+                    installed_tools: HashMap::new(),
+                    download_queue: Vec::new(),
+                    download_queue_paused: false,
                 }
             }
         }
@@ -338,6 +547,12 @@ pub mod data {
                     game_versions: HashMap::new(),
                     installed_game_version: HashMap::new(),
                     transient_statuses: HashMap::new(),
+                    installed_dlc: HashMap::new(),
+                    installed_mods: HashMap::new(),
+                    deployed_mod_files: HashMap::new(),
+                    installed_tools: HashMap::new(),
+                    download_queue: Vec::new(),
+                    download_queue_paused: false,
                 },
                 prev_database,
                 base_url: String::new(),
@@ -8,7 +8,7 @@ use crate::{
         models::data::v1::{DownloadType, DownloadableMetadata},
     },
     games::{
-        downloads::drop_data::{v1::DropData, DROP_DATA_PATH},
+        downloads::drop_data::{DROP_DATA_PATH, DropData},
         library::set_partially_installed_db,
     },
 };
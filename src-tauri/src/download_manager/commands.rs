@@ -1,6 +1,6 @@
 use std::sync::Mutex;
 
-use crate::{database::models::data::DownloadableMetadata, AppState};
+use crate::{AppState, database::models::data::DownloadableMetadata};
 
 #[tauri::command]
 pub fn pause_downloads(state: tauri::State<'_, Mutex<AppState>>) {
@@ -29,3 +29,21 @@ pub fn move_download_in_queue(
 pub fn cancel_game(state: tauri::State<'_, Mutex<AppState>>, meta: DownloadableMetadata) {
     state.lock().unwrap().download_manager.cancel(meta);
 }
+
+#[tauri::command]
+pub fn pause_queued_download(state: tauri::State<'_, Mutex<AppState>>, meta: DownloadableMetadata) {
+    state.lock().unwrap().download_manager.pause_download(meta);
+}
+
+#[tauri::command]
+pub fn resume_queued_download(
+    state: tauri::State<'_, Mutex<AppState>>,
+    meta: DownloadableMetadata,
+) {
+    state.lock().unwrap().download_manager.resume_download(meta);
+}
+
+#[tauri::command]
+pub fn download_next(state: tauri::State<'_, Mutex<AppState>>, meta: DownloadableMetadata) {
+    state.lock().unwrap().download_manager.download_next(meta);
+}
@@ -1,28 +1,36 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     sync::{
         Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
         mpsc::{Receiver, Sender, channel},
     },
-    thread::{JoinHandle, spawn},
+    thread::{JoinHandle, sleep, spawn},
 };
 
 use log::{debug, error, info, warn};
 use tauri::{AppHandle, Emitter};
 
 use crate::{
-    database::models::data::DownloadableMetadata,
+    database::{
+        db::{borrow_db_checked, borrow_db_mut_checked},
+        models::data::{DownloadHookEvent, DownloadableMetadata, QueuedDownload},
+    },
     error::application_download_error::ApplicationDownloadError,
     games::library::{QueueUpdateEvent, QueueUpdateEventQueueData, StatsUpdateEvent},
+    remote::utils::server_reachable,
 };
 
 use super::{
     download_manager_frontend::{DownloadManager, DownloadManagerSignal, DownloadManagerStatus},
     downloadable::Downloadable,
     util::{
-        download_thread_control_flag::{DownloadThreadControl, DownloadThreadControlFlag},
+        download_schedule::{watch_download_schedule, within_download_window},
+        download_thread_control_flag::DownloadThreadControlFlag,
+        hooks::{HookContext, run_download_hooks},
         progress_object::ProgressObject,
         queue::Queue,
+        rate_limiter::DOWNLOAD_RATE_LIMITER,
     },
 };
 
@@ -34,8 +42,9 @@ pub type CurrentProgressObject = Arc<Mutex<Option<Arc<ProgressObject>>>>;
 Welcome to the download manager, the most overengineered, glorious piece of bullshit.
 
 The download manager takes a queue of ids and their associated
-DownloadAgents, and then, one-by-one, executes them. It provides an interface
-to interact with the currently downloading agent, and manage the queue.
+DownloadAgents, and then executes them from the front of the queue, up to
+max_concurrent_downloads at a time. It provides an interface to interact
+with the currently downloading agents, and manage the queue.
 
 When the DownloadManager is initialised, it is designed to provide a reference
 which can be used to provide some instructions (the DownloadManagerInterface),
@@ -66,6 +75,11 @@ Behold, my madness - quexeky
 
 */
 
+struct ActiveDownload {
+    agent: DownloadAgent,
+    thread: Option<JoinHandle<()>>,
+}
+
 pub struct DownloadManagerBuilder {
     download_agent_registry: HashMap<DownloadableMetadata, DownloadAgent>,
     download_queue: Queue,
@@ -75,9 +89,23 @@ pub struct DownloadManagerBuilder {
     status: Arc<Mutex<DownloadManagerStatus>>,
     app_handle: AppHandle,
 
-    current_download_agent: Option<DownloadAgent>, // Should be the only download agent in the map with the "Go" flag
-    current_download_thread: Mutex<Option<JoinHandle<()>>>,
-    active_control_flag: Option<DownloadThreadControl>,
+    // Should be the only download agents in the map with the "Go" flag
+    active_downloads: HashMap<DownloadableMetadata, ActiveDownload>,
+    // Queued, but skipped over until they're resumed
+    paused_downloads: HashSet<DownloadableMetadata>,
+
+    // Waiting for the server to come back after a network error, and skipped
+    // over until it does. Clearing the flag stops the wait.
+    reconnecting: HashMap<DownloadableMetadata, Arc<AtomicBool>>,
+    auto_resume_attempts: HashMap<DownloadableMetadata, usize>,
+
+    // The queue last written to the database, and whether each was paused
+    persisted_queue: Option<Vec<(DownloadableMetadata, bool)>>,
+    // Set until the queue from the last run is rebuilt, so what's stored
+    // doesn't get overwritten halfway through
+    restoring: bool,
+    // Couldn't be rebuilt this time, so they're kept to try again next run
+    unrestored: Vec<QueuedDownload>,
 }
 impl DownloadManagerBuilder {
     pub fn build(app_handle: AppHandle) -> DownloadManager {
@@ -86,6 +114,15 @@ impl DownloadManagerBuilder {
         let active_progress = Arc::new(Mutex::new(None));
         let status = Arc::new(Mutex::new(DownloadManagerStatus::Empty));
 
+        let (max_download_speed, restoring) = {
+            let db_lock = borrow_db_checked();
+            (
+                db_lock.settings.max_download_speed,
+                !db_lock.applications.download_queue.is_empty(),
+            )
+        };
+        DOWNLOAD_RATE_LIMITER.set_limit(max_download_speed);
+
         let manager = Self {
             download_agent_registry: HashMap::new(),
             download_queue: queue.clone(),
@@ -95,13 +132,22 @@ impl DownloadManagerBuilder {
             progress: active_progress.clone(),
             app_handle,
 
-            current_download_agent: None,
-            current_download_thread: Mutex::new(None),
-            active_control_flag: None,
+            active_downloads: HashMap::new(),
+            paused_downloads: HashSet::new(),
+
+            reconnecting: HashMap::new(),
+            auto_resume_attempts: HashMap::new(),
+
+            persisted_queue: None,
+            restoring,
+            unrestored: Vec::new(),
         };
 
         let terminator = spawn(|| manager.manage_queue());
 
+        let schedule_sender = command_sender.clone();
+        spawn(move || watch_download_schedule(schedule_sender));
+
         DownloadManager::new(terminator, queue, active_progress, command_sender)
     }
 
@@ -109,44 +155,79 @@ impl DownloadManagerBuilder {
         *self.status.lock().unwrap() = status;
     }
 
-    fn remove_and_cleanup_front_download(&mut self, meta: &DownloadableMetadata) -> DownloadAgent {
-        self.download_queue.pop_front();
-        let download_agent = self.download_agent_registry.remove(meta).unwrap();
-        self.cleanup_current_download();
-        download_agent
+    // Stopped as a whole, by the user or the download schedule, so nothing
+    // should start until the next Go from them
+    fn queue_paused(&self) -> bool {
+        matches!(*self.status.lock().unwrap(), DownloadManagerStatus::Paused)
     }
 
-    // CAREFUL WITH THIS FUNCTION
-    // Make sure the download thread is terminated
-    fn cleanup_current_download(&mut self) {
-        self.active_control_flag = None;
-        *self.progress.lock().unwrap() = None;
-        self.current_download_agent = None;
+    fn remove_and_cleanup_download(
+        &mut self,
+        meta: &DownloadableMetadata,
+    ) -> Option<DownloadAgent> {
+        if let Some(index) = self.download_queue.get_by_meta(meta) {
+            self.download_queue.edit().remove(index);
+        }
+        self.cleanup_download(meta);
+        self.paused_downloads.remove(meta);
+        self.stop_reconnecting(meta);
+        self.auto_resume_attempts.remove(meta);
+        let removed = self.download_agent_registry.remove(meta);
+
+        if removed.is_some() && self.download_queue.read().is_empty() {
+            run_download_hooks(
+                &self.app_handle,
+                DownloadHookEvent::QueueEmpty,
+                HookContext {
+                    meta: None,
+                    install_dir: None,
+                    error: None,
+                },
+            );
+        }
 
-        let mut download_thread_lock = self.current_download_thread.lock().unwrap();
+        removed
+    }
 
-        if let Some(unfinished_thread) = download_thread_lock.take()
+    // CAREFUL WITH THIS FUNCTION
+    // Make sure the download thread is terminated
+    fn cleanup_download(&mut self, meta: &DownloadableMetadata) {
+        if let Some(active) = self.active_downloads.remove(meta)
+            && let Some(unfinished_thread) = active.thread
             && !unfinished_thread.is_finished()
         {
             unfinished_thread.join().unwrap();
         }
-        drop(download_thread_lock);
-    }
 
-    fn stop_and_wait_current_download(&self) -> bool {
-        self.set_status(DownloadManagerStatus::Paused);
-        if let Some(current_flag) = &self.active_control_flag {
-            current_flag.set(DownloadThreadControlFlag::Stop);
+        if self.active_downloads.is_empty() {
+            *self.progress.lock().unwrap() = None;
         }
+    }
 
-        let mut download_thread_lock = self.current_download_thread.lock().unwrap();
-        if let Some(current_download_thread) = download_thread_lock.take() {
-            return current_download_thread.join().is_ok();
+    fn stop_and_wait_download(&mut self, meta: &DownloadableMetadata) -> bool {
+        let Some(active) = self.active_downloads.get_mut(meta) else {
+            return true;
         };
+        active
+            .agent
+            .control_flag()
+            .set(DownloadThreadControlFlag::Stop);
+
+        if let Some(download_thread) = active.thread.take() {
+            return download_thread.join().is_ok();
+        }
 
         true
     }
 
+    fn stop_and_wait_all_downloads(&mut self) {
+        self.set_status(DownloadManagerStatus::Paused);
+        let active = self.active_downloads.keys().cloned().collect::<Vec<_>>();
+        for meta in active {
+            self.stop_and_wait_download(&meta);
+        }
+    }
+
     fn manage_queue(mut self) -> Result<(), ()> {
         loop {
             let signal = match self.command_receiver.recv() {
@@ -167,8 +248,14 @@ impl DownloadManagerBuilder {
                 DownloadManagerSignal::Queue(download_agent) => {
                     self.manage_queue_signal(download_agent);
                 }
-                DownloadManagerSignal::Error(e) => {
-                    self.manage_error_signal(e);
+                DownloadManagerSignal::Error(meta, e) => {
+                    self.manage_error_signal(meta, e);
+                }
+                DownloadManagerSignal::Reconnected(meta) => {
+                    self.manage_reconnected_signal(&meta);
+                }
+                DownloadManagerSignal::Restored(unrestored) => {
+                    self.manage_restored_signal(unrestored);
                 }
                 DownloadManagerSignal::UpdateUIQueue => {
                     self.push_ui_queue_update();
@@ -177,12 +264,21 @@ impl DownloadManagerBuilder {
                     self.push_ui_stats_update(kbs, time);
                 }
                 DownloadManagerSignal::Finish => {
-                    self.stop_and_wait_current_download();
+                    self.stop_and_wait_all_downloads();
                     return Ok(());
                 }
                 DownloadManagerSignal::Cancel(meta) => {
                     self.manage_cancel_signal(&meta);
                 }
+                DownloadManagerSignal::Pause(meta) => {
+                    self.manage_pause_signal(meta);
+                }
+                DownloadManagerSignal::Resume(meta) => {
+                    self.manage_resume_signal(&meta);
+                }
+                DownloadManagerSignal::Prioritise(meta) => {
+                    self.manage_prioritise_signal(&meta);
+                }
             }
         }
     }
@@ -198,6 +294,8 @@ impl DownloadManagerBuilder {
         }
 
         download_agent.on_initialised(&self.app_handle);
+        // Queued again some other way, so there's nothing left to restore
+        self.unrestored.retain(|entry| entry.meta != meta);
         self.download_queue.append(meta.clone());
         self.download_agent_registry.insert(meta, download_agent);
 
@@ -208,6 +306,7 @@ impl DownloadManagerBuilder {
 
     fn manage_go_signal(&mut self) {
         debug!("got signal Go");
+        persist_paused(false);
         if self.download_agent_registry.is_empty() {
             debug!(
                 "Download agent registry: {:?}",
@@ -216,35 +315,92 @@ impl DownloadManagerBuilder {
             return;
         }
 
-        if self.current_download_agent.is_some()
-            && self.download_queue.read().front().unwrap()
-                == &self.current_download_agent.as_ref().unwrap().metadata()
-        {
+        let (within_window, max_concurrent_downloads, max_download_threads) = {
+            let db_lock = borrow_db_checked();
+            (
+                within_download_window(&db_lock.settings),
+                db_lock.settings.max_concurrent_downloads.max(1),
+                db_lock.settings.max_download_threads,
+            )
+        };
+        if !within_window {
+            // Not a pause by the user, so don't persist it
+            info!("outside of scheduled download window, waiting for it to open");
+            self.stop_and_wait_all_downloads();
             return;
         }
 
         debug!("current download queue: {:?}", self.download_queue.read());
 
-        // Should always be Some if the above two statements keep going
-        let agent_data = self.download_queue.read().front().unwrap().clone();
+        let front = self
+            .download_queue
+            .read()
+            .into_iter()
+            .filter(|meta| {
+                !self.paused_downloads.contains(meta) && !self.reconnecting.contains_key(meta)
+            })
+            .take(max_concurrent_downloads)
+            .collect::<Vec<DownloadableMetadata>>();
+
+        // Anything that's been moved back in the queue makes room
+        let displaced = self
+            .active_downloads
+            .keys()
+            .filter(|meta| !front.contains(meta))
+            .cloned()
+            .collect::<Vec<DownloadableMetadata>>();
+        for meta in displaced {
+            info!("pausing {meta:?} as it's no longer at the front of the queue");
+            self.stop_and_wait_download(&meta);
+            self.active_downloads.remove(&meta);
+        }
 
-        info!("starting download for {agent_data:?}");
+        // Split the thread budget between everything running at once
+        let thread_budget = (max_download_threads / front.len().max(1)).max(1);
+
+        for meta in &front {
+            let download_agent = self.download_agent_registry.get(meta).unwrap().clone();
+            download_agent.set_thread_budget(thread_budget);
 
-        let download_agent = self
-            .download_agent_registry
-            .get(&agent_data)
-            .unwrap()
-            .clone();
+            if let Some(active) = self.active_downloads.get_mut(meta) {
+                if download_agent.control_flag().get() == DownloadThreadControlFlag::Go {
+                    continue;
+                }
 
-        self.active_control_flag = Some(download_agent.control_flag());
-        self.current_download_agent = Some(download_agent.clone());
+                // Paused (e.g. by the download schedule), so let the old thread
+                // wind down before starting it back up
+                if let Some(stopped_thread) = active.thread.take() {
+                    let _ = stopped_thread.join();
+                }
+            }
 
-        let sender = self.sender.clone();
+            self.start_download(download_agent);
+        }
+
+        *self.progress.lock().unwrap() = front
+            .first()
+            .and_then(|meta| self.active_downloads.get(meta))
+            .map(|active| active.agent.progress());
+
+        if front.is_empty() {
+            // Everything left is paused, or still waiting on the server
+            if self.reconnecting.is_empty() {
+                self.set_status(DownloadManagerStatus::Empty);
+            }
+            return;
+        }
+        self.set_status(DownloadManagerStatus::Downloading);
+    }
+    fn start_download(&mut self, download_agent: DownloadAgent) {
+        let agent_data = download_agent.metadata();
+        info!("starting download for {agent_data:?}");
 
-        let mut download_thread_lock = self.current_download_thread.lock().unwrap();
+        let sender = self.sender.clone();
         let app_handle = self.app_handle.clone();
+        let thread_agent = download_agent.clone();
 
-        *download_thread_lock = Some(spawn(move || {
+        let download_thread = spawn(move || {
+            let download_agent = thread_agent;
             loop {
                 let download_result = match download_agent.download(&app_handle) {
                     // Ok(true) is for completed and exited properly
@@ -252,7 +408,9 @@ impl DownloadManagerBuilder {
                     Err(e) => {
                         error!("download {:?} has error {}", download_agent.metadata(), &e);
                         download_agent.on_error(&app_handle, &e);
-                        sender.send(DownloadManagerSignal::Error(e)).unwrap();
+                        sender
+                            .send(DownloadManagerSignal::Error(download_agent.metadata(), e))
+                            .unwrap();
                         return;
                     }
                 };
@@ -276,7 +434,9 @@ impl DownloadManagerBuilder {
                             &e
                         );
                         download_agent.on_error(&app_handle, &e);
-                        sender.send(DownloadManagerSignal::Error(e)).unwrap();
+                        sender
+                            .send(DownloadManagerSignal::Error(download_agent.metadata(), e))
+                            .unwrap();
                         return;
                     }
                 };
@@ -294,91 +454,301 @@ impl DownloadManagerBuilder {
                     return;
                 }
             }
-        }));
+        });
 
-        self.set_status(DownloadManagerStatus::Downloading);
-        let active_control_flag = self.active_control_flag.clone().unwrap();
-        active_control_flag.set(DownloadThreadControlFlag::Go);
+        download_agent
+            .control_flag()
+            .set(DownloadThreadControlFlag::Go);
+        self.active_downloads.insert(
+            agent_data,
+            ActiveDownload {
+                agent: download_agent,
+                thread: Some(download_thread),
+            },
+        );
     }
     fn manage_stop_signal(&mut self) {
         debug!("got signal Stop");
+        persist_paused(true);
+        // Don't resume behind the user's back
+        let reconnecting = self.reconnecting.keys().cloned().collect::<Vec<_>>();
+        for meta in reconnecting {
+            self.stop_reconnecting(&meta);
+        }
 
-        if let Some(active_control_flag) = self.active_control_flag.clone() {
-            self.set_status(DownloadManagerStatus::Paused);
-            active_control_flag.set(DownloadThreadControlFlag::Stop);
+        self.set_status(DownloadManagerStatus::Paused);
+        if !self.active_downloads.is_empty() {
+            for active in self.active_downloads.values() {
+                active
+                    .agent
+                    .control_flag()
+                    .set(DownloadThreadControlFlag::Stop);
+            }
         }
     }
     fn manage_completed_signal(&mut self, meta: DownloadableMetadata) {
         debug!("got signal Completed");
-        if let Some(interface) = &self.current_download_agent
-            && interface.metadata() == meta
+        if let Some(install_dir) = self
+            .active_downloads
+            .get(&meta)
+            .map(|active| active.agent.install_dir())
         {
-            self.remove_and_cleanup_front_download(&meta);
+            // Before it's removed, so this runs ahead of QueueEmpty
+            run_download_hooks(
+                &self.app_handle,
+                DownloadHookEvent::Completed,
+                HookContext {
+                    meta: Some(&meta),
+                    install_dir: Some(&install_dir),
+                    error: None,
+                },
+            );
+            self.remove_and_cleanup_download(&meta);
         }
 
         self.push_ui_queue_update();
         self.sender.send(DownloadManagerSignal::Go).unwrap();
     }
-    fn manage_error_signal(&mut self, error: ApplicationDownloadError) {
+    fn manage_error_signal(&mut self, meta: DownloadableMetadata, error: ApplicationDownloadError) {
         debug!("got signal Error");
-        if let Some(current_agent) = self.current_download_agent.clone() {
-            current_agent.on_error(&self.app_handle, &error);
+        // Every thread that hit the same outage reports it, but it's
+        // already waiting on the server rather than failed
+        if self.reconnecting.contains_key(&meta) {
+            return;
+        }
+        if let Some(agent) = self
+            .active_downloads
+            .get(&meta)
+            .map(|active| active.agent.clone())
+        {
+            agent.on_error(&self.app_handle, &error);
+            let install_dir = agent.install_dir();
+            run_download_hooks(
+                &self.app_handle,
+                DownloadHookEvent::Error,
+                HookContext {
+                    meta: Some(&meta),
+                    install_dir: Some(&install_dir),
+                    error: Some(error.to_string()),
+                },
+            );
 
-            self.stop_and_wait_current_download();
-            self.remove_and_cleanup_front_download(&current_agent.metadata());
+            self.stop_and_wait_download(&meta);
+            if let ApplicationDownloadError::DiskFull(..) = error {
+                // Leave it queued, to pick up once there's room again
+                info!("out of disk space, pausing downloads");
+                self.manage_stop_signal();
+                self.push_ui_queue_update();
+                return;
+            }
+            if !(error.is_network_error() && self.resume_when_reachable(&meta)) {
+                self.remove_and_cleanup_download(&meta);
+            }
         }
         self.push_ui_queue_update();
-        self.set_status(DownloadManagerStatus::Error);
+
+        let still_downloading = self
+            .active_downloads
+            .values()
+            .any(|active| active.agent.control_flag().get() == DownloadThreadControlFlag::Go);
+        if !still_downloading {
+            self.set_status(DownloadManagerStatus::Error);
+        }
+    }
+    /// Leaves the errored download in the queue, and picks it back up once
+    /// the server answers again. Returns false if we've given up on it.
+    fn resume_when_reachable(&mut self, meta: &DownloadableMetadata) -> bool {
+        let retry_policy = borrow_db_checked().settings.retry_policy.clone();
+        let attempts = self.auto_resume_attempts.entry(meta.clone()).or_default();
+        if *attempts >= retry_policy.max_attempts {
+            return false;
+        }
+        *attempts += 1;
+
+        info!("lost connection to the server, resuming {meta:?} once it's reachable");
+
+        let reconnecting = Arc::new(AtomicBool::new(true));
+        self.reconnecting.insert(meta.clone(), reconnecting.clone());
+        let sender = self.sender.clone();
+        let meta = meta.clone();
+        spawn(move || {
+            let mut attempt = 0;
+            loop {
+                sleep(retry_policy.backoff(attempt));
+                // Paused or cancelled while we were waiting
+                if !reconnecting.load(Ordering::Acquire) {
+                    return;
+                }
+                if server_reachable() {
+                    break;
+                }
+                attempt += 1;
+            }
+
+            let _ = sender.send(DownloadManagerSignal::Reconnected(meta));
+        });
+
+        true
+    }
+    fn manage_reconnected_signal(&mut self, meta: &DownloadableMetadata) {
+        debug!("got signal Reconnected");
+        let Some(reconnecting) = self.reconnecting.remove(meta) else {
+            return;
+        };
+        if !reconnecting.load(Ordering::Acquire) {
+            return;
+        }
+
+        info!("server is reachable again, resuming {meta:?}");
+        self.sender.send(DownloadManagerSignal::Go).unwrap();
+    }
+    /// Gives up waiting for the server on behalf of `meta`, if we were
+    fn stop_reconnecting(&mut self, meta: &DownloadableMetadata) {
+        if let Some(reconnecting) = self.reconnecting.remove(meta) {
+            reconnecting.store(false, Ordering::Release);
+        }
+    }
+    fn manage_restored_signal(&mut self, unrestored: Vec<QueuedDownload>) {
+        debug!("got signal Restored");
+        self.restoring = false;
+        self.unrestored = unrestored
+            .into_iter()
+            .filter(|entry| !self.download_agent_registry.contains_key(&entry.meta))
+            .collect();
+
+        // Whatever's stored is from the last run, so always replace it
+        self.persisted_queue = None;
+        self.persist_queue();
     }
     fn manage_cancel_signal(&mut self, meta: &DownloadableMetadata) {
         debug!("got signal Cancel");
 
-        if let Some(current_download) = &self.current_download_agent {
-            if &current_download.metadata() == meta {
-                self.set_status(DownloadManagerStatus::Paused);
-                current_download.on_cancelled(&self.app_handle);
-                self.stop_and_wait_current_download();
+        if let Some(download_agent) = self.download_agent_registry.get(meta).cloned() {
+            let was_active = self.active_downloads.contains_key(meta);
 
-                self.download_queue.pop_front();
+            download_agent.on_cancelled(&self.app_handle);
+            self.stop_and_wait_download(meta);
+            let removed = self.remove_and_cleanup_download(meta);
+            debug!(
+                "removed {:?} from queue {:?}",
+                removed.map(|x| x.metadata()),
+                self.download_queue.read()
+            );
 
-                self.cleanup_current_download();
-                debug!("current download queue: {:?}", self.download_queue.read());
-            }
-            // TODO: Collapse these two into a single if statement somehow
-            else if let Some(download_agent) = self.download_agent_registry.get(meta) {
-                let index = self.download_queue.get_by_meta(meta);
-                if let Some(index) = index {
-                    download_agent.on_cancelled(&self.app_handle);
-                    let _ = self.download_queue.edit().remove(index).unwrap();
-                    let removed = self.download_agent_registry.remove(meta);
-                    debug!(
-                        "removed {:?} from queue {:?}",
-                        removed.map(|x| x.metadata()),
-                        self.download_queue.read()
-                    );
-                }
+            // Fill the slot it left with whatever's next
+            if was_active && !self.queue_paused() {
+                self.sender.send(DownloadManagerSignal::Go).unwrap();
             }
-        } else if let Some(download_agent) = self.download_agent_registry.get(meta) {
-            let index = self.download_queue.get_by_meta(meta);
-            if let Some(index) = index {
-                download_agent.on_cancelled(&self.app_handle);
-                let _ = self.download_queue.edit().remove(index).unwrap();
-                let removed = self.download_agent_registry.remove(meta);
-                debug!(
-                    "removed {:?} from queue {:?}",
-                    removed.map(|x| x.metadata()),
-                    self.download_queue.read()
-                );
+        }
+        self.push_ui_queue_update();
+    }
+    fn manage_pause_signal(&mut self, meta: DownloadableMetadata) {
+        debug!("got signal Pause");
+        if !self.download_agent_registry.contains_key(&meta) {
+            return;
+        }
+
+        self.paused_downloads.insert(meta.clone());
+        self.stop_reconnecting(&meta);
+        if self.active_downloads.contains_key(&meta) {
+            // Stopping writes its progress to its DropData, so it resumes where it left off
+            self.stop_and_wait_download(&meta);
+            self.cleanup_download(&meta);
+
+            // Give its slot to whatever's next, unless everything's paused
+            if !self.queue_paused() {
+                self.sender.send(DownloadManagerSignal::Go).unwrap();
             }
         }
         self.push_ui_queue_update();
     }
+    fn manage_resume_signal(&mut self, meta: &DownloadableMetadata) {
+        debug!("got signal Resume");
+        // Picked up with everything else once the queue is resumed
+        if self.paused_downloads.remove(meta) && !self.queue_paused() {
+            self.sender.send(DownloadManagerSignal::Go).unwrap();
+        }
+        self.push_ui_queue_update();
+    }
+    fn manage_prioritise_signal(&mut self, meta: &DownloadableMetadata) {
+        debug!("got signal Prioritise");
+        let Some(index) = self.download_queue.get_by_meta(meta) else {
+            return;
+        };
+
+        self.paused_downloads.remove(meta);
+        // Try it again straight away, rather than waiting on the server
+        self.stop_reconnecting(meta);
+        {
+            let mut queue = self.download_queue.edit();
+            let to_move = queue.remove(index).unwrap();
+            queue.push_front(to_move);
+        }
+
+        // Go pauses whatever this displaces. With the queue paused,
+        // it just waits at the front.
+        if !self.queue_paused() {
+            self.sender.send(DownloadManagerSignal::Go).unwrap();
+        }
+        self.push_ui_queue_update();
+    }
     fn push_ui_stats_update(&self, kbs: usize, time: usize) {
-        let event_data = StatsUpdateEvent { speed: kbs, time };
+        // Each download only reports its own stats, so combine them
+        let (speed, time) = if self.active_downloads.len() > 1 {
+            let (speed, remaining) = self
+                .active_downloads
+                .values()
+                .map(|active| active.agent.progress())
+                .fold((0, 0), |(speed, remaining), progress| {
+                    (
+                        speed + progress.get_speed(),
+                        remaining + progress.get_max().saturating_sub(progress.sum()),
+                    )
+                });
+            (speed, (remaining / 1000) / speed.max(1))
+        } else {
+            (kbs, time)
+        };
+
+        let event_data = StatsUpdateEvent { speed, time };
 
         self.app_handle.emit("update_stats", event_data).unwrap();
     }
-    fn push_ui_queue_update(&self) {
+    /// Writes the queue to the database whenever its contents or order
+    /// change, so it can be rebuilt after a restart
+    fn persist_queue(&mut self) {
+        if self.restoring {
+            return;
+        }
+
+        let queue = self
+            .download_queue
+            .read()
+            .into_iter()
+            .map(|meta| {
+                let paused = self.paused_downloads.contains(&meta);
+                (meta, paused)
+            })
+            .collect::<Vec<_>>();
+        if self.persisted_queue.as_ref() == Some(&queue) {
+            return;
+        }
+
+        let entries = queue
+            .iter()
+            .filter_map(|(meta, paused)| {
+                let mut entry = self.download_agent_registry.get(meta)?.queue_entry();
+                entry.paused = *paused;
+                Some(entry)
+            })
+            .chain(self.unrestored.iter().cloned())
+            .collect();
+        borrow_db_mut_checked().applications.download_queue = entries;
+        self.persisted_queue = Some(queue);
+    }
+    fn push_ui_queue_update(&mut self) {
+        self.persist_queue();
+
         let queue = &self.download_queue.read();
         let queue_objs = queue
             .iter()
@@ -390,6 +760,7 @@ impl DownloadManagerBuilder {
                     progress: val.progress().get_progress(),
                     current: val.progress().sum(),
                     max: val.progress().get_max(),
+                    paused: self.paused_downloads.contains(key),
                 }
             })
             .collect();
@@ -398,3 +769,9 @@ impl DownloadManagerBuilder {
         self.app_handle.emit("update_queue", event_data).unwrap();
     }
 }
+
+fn persist_paused(paused: bool) {
+    if borrow_db_checked().applications.download_queue_paused != paused {
+        borrow_db_mut_checked().applications.download_queue_paused = paused;
+    }
+}
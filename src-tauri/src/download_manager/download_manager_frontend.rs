@@ -3,8 +3,8 @@ use std::{
     collections::VecDeque,
     fmt::Debug,
     sync::{
-        mpsc::{SendError, Sender},
         Mutex, MutexGuard,
+        mpsc::{SendError, Sender},
     },
     thread::JoinHandle,
 };
@@ -13,7 +13,7 @@ use log::{debug, info};
 use serde::Serialize;
 
 use crate::{
-    database::models::data::DownloadableMetadata,
+    database::models::data::{DownloadableMetadata, QueuedDownload},
     error::application_download_error::ApplicationDownloadError,
 };
 
@@ -38,8 +38,22 @@ pub enum DownloadManagerSignal {
     Finish,
     /// Stops, removes, and tells a download to cleanup
     Cancel(DownloadableMetadata),
-    /// Any error which occurs in the agent
-    Error(ApplicationDownloadError),
+    /// Stops a single download, and skips over it
+    /// in the queue until it's resumed
+    Pause(DownloadableMetadata),
+    /// Lets a paused download run again
+    Resume(DownloadableMetadata),
+    /// Moves a download to the front of the queue,
+    /// pausing whatever it displaces
+    Prioritise(DownloadableMetadata),
+    /// Any error which occurs in an agent
+    Error(DownloadableMetadata, ApplicationDownloadError),
+    /// The queue from the last run has been rebuilt,
+    /// except for these entries that couldn't be
+    Restored(Vec<QueuedDownload>),
+    /// The server is reachable again, so a download
+    /// that lost its connection can carry on
+    Reconnected(DownloadableMetadata),
     /// Pushes UI update
     UpdateUIQueue,
     UpdateUIStats(usize, usize), //kb/s and seconds
@@ -112,6 +126,31 @@ impl DownloadManager {
             .send(DownloadManagerSignal::Queue(download))?;
         self.command_sender.send(DownloadManagerSignal::Go)
     }
+    /// Lets the manager save the queue again, once everything
+    /// from the last run has been restored
+    pub fn finish_restore(
+        &self,
+        unrestored: Vec<QueuedDownload>,
+    ) -> Result<(), SendError<DownloadManagerSignal>> {
+        self.command_sender
+            .send(DownloadManagerSignal::Restored(unrestored))
+    }
+    /// Queues without starting anything, for rebuilding the queue on startup
+    pub fn restore_download(
+        &self,
+        download: DownloadAgent,
+        paused: bool,
+    ) -> Result<(), SendError<DownloadManagerSignal>> {
+        info!("restoring download with meta {:?}", download.metadata());
+        let meta = download.metadata();
+        self.command_sender
+            .send(DownloadManagerSignal::Queue(download))?;
+        if paused {
+            self.command_sender
+                .send(DownloadManagerSignal::Pause(meta))?;
+        }
+        Ok(())
+    }
     pub fn edit(&self) -> MutexGuard<'_, VecDeque<DownloadableMetadata>> {
         self.download_queue.edit()
     }
@@ -163,6 +202,24 @@ impl DownloadManager {
             .unwrap();
         self.command_sender.send(DownloadManagerSignal::Go).unwrap();
     }
+    pub fn pause_download(&self, meta: DownloadableMetadata) {
+        self.command_sender
+            .send(DownloadManagerSignal::Pause(meta))
+            .unwrap();
+    }
+    pub fn resume_download(&self, meta: DownloadableMetadata) {
+        self.command_sender
+            .send(DownloadManagerSignal::Resume(meta))
+            .unwrap();
+    }
+    /// Gracefully preempts whatever's downloading, which picks
+    /// up where it left off once this is done. If the queue is
+    /// paused, only moves it to the front.
+    pub fn download_next(&self, meta: DownloadableMetadata) {
+        self.command_sender
+            .send(DownloadManagerSignal::Prioritise(meta))
+            .unwrap();
+    }
     pub fn pause_downloads(&self) {
         self.command_sender
             .send(DownloadManagerSignal::Stop)
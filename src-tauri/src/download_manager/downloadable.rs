@@ -1,9 +1,9 @@
-use std::sync::Arc;
+use std::{path::PathBuf, sync::Arc};
 
 use tauri::AppHandle;
 
 use crate::{
-    database::models::data::DownloadableMetadata,
+    database::models::data::{DownloadableMetadata, QueuedDownload},
     error::application_download_error::ApplicationDownloadError,
 };
 
@@ -18,8 +18,15 @@ pub trait Downloadable: Send + Sync {
 
     fn progress(&self) -> Arc<ProgressObject>;
     fn control_flag(&self) -> DownloadThreadControl;
+    /// How many threads this download may use, when it's sharing
+    /// `max_download_threads` with other downloads
+    fn set_thread_budget(&self, threads: usize);
     fn status(&self) -> DownloadStatus;
     fn metadata(&self) -> DownloadableMetadata;
+    /// What's needed to queue this again after a restart
+    fn queue_entry(&self) -> QueuedDownload;
+    /// Where this download's files end up
+    fn install_dir(&self) -> PathBuf;
     fn on_initialised(&self, app_handle: &AppHandle);
     fn on_error(&self, app_handle: &AppHandle, error: &ApplicationDownloadError);
     fn on_complete(&self, app_handle: &AppHandle);
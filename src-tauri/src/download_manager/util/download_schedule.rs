@@ -0,0 +1,66 @@
+use std::{sync::mpsc::Sender, thread::sleep, time::Duration};
+
+use chrono::{Local, Timelike};
+use log::info;
+
+use crate::{
+    database::{db::borrow_db_checked, models::data::Settings},
+    download_manager::download_manager_frontend::DownloadManagerSignal,
+};
+
+const SCHEDULE_POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Always true if there's no schedule set up
+pub fn within_download_window(settings: &Settings) -> bool {
+    let Some(schedule) = &settings.download_schedule else {
+        return true;
+    };
+
+    let now = Local::now();
+    let minute = now.hour() * 60 + now.minute();
+
+    if schedule.start <= schedule.end {
+        schedule.start <= minute && minute < schedule.end
+    } else {
+        schedule.start <= minute || minute < schedule.end
+    }
+}
+
+/// Pauses and resumes the queue as the download window opens and closes.
+/// Go works out which from the window itself, so this never marks the
+/// queue as paused by the user, and leaves it alone if they have.
+pub fn watch_download_schedule(sender: Sender<DownloadManagerSignal>) {
+    let mut was_within = within_download_window(&borrow_db_checked().settings);
+
+    loop {
+        sleep(SCHEDULE_POLL_INTERVAL);
+
+        let (within, paused) = {
+            let db_lock = borrow_db_checked();
+            (
+                within_download_window(&db_lock.settings),
+                db_lock.applications.download_queue_paused,
+            )
+        };
+        if within == was_within {
+            continue;
+        }
+        was_within = within;
+
+        if paused {
+            info!("download window changed, but downloads are paused");
+            continue;
+        }
+
+        if within {
+            info!("download window opened, resuming downloads");
+        } else {
+            info!("download window closed, pausing downloads");
+        }
+
+        // Manager has shut down
+        if sender.send(DownloadManagerSignal::Go).is_err() {
+            return;
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use std::{path::Path, process::Command, thread::spawn};
+
+use log::{info, warn};
+use tauri::AppHandle;
+use tauri_plugin_notification::NotificationExt;
+
+use crate::{
+    database::{
+        db::borrow_db_checked,
+        models::data::{DownloadHookAction, DownloadHookEvent, DownloadableMetadata},
+    },
+    games::library::Game,
+    remote::cache::get_cached_object,
+};
+
+/// What a hook gets told about the download that triggered it
+pub struct HookContext<'a> {
+    pub meta: Option<&'a DownloadableMetadata>,
+    pub install_dir: Option<&'a Path>,
+    pub error: Option<String>,
+}
+
+/// Runs every hook set up for `event`. Commands run in the background,
+/// so a slow hook never holds up the queue.
+pub fn run_download_hooks(app_handle: &AppHandle, event: DownloadHookEvent, context: HookContext) {
+    let hooks = borrow_db_checked()
+        .settings
+        .download_hooks
+        .iter()
+        .filter(|hook| hook.event == event)
+        .map(|hook| hook.action.clone())
+        .collect::<Vec<_>>();
+
+    for action in hooks {
+        match action {
+            DownloadHookAction::Command { program, args } => {
+                run_command_hook(event, &context, program, args)
+            }
+            DownloadHookAction::Notification => notify(app_handle, event, &context),
+        }
+    }
+}
+
+fn run_command_hook(
+    event: DownloadHookEvent,
+    context: &HookContext,
+    program: String,
+    args: Vec<String>,
+) {
+    let mut command = Command::new(&program);
+    command
+        .args(args)
+        .env("DROP_HOOK_EVENT", format!("{event:?}"))
+        .env_remove("RUST_LOG");
+    if let Some(meta) = context.meta {
+        command
+            .env("DROP_GAME_ID", &meta.id)
+            .env(
+                "DROP_GAME_VERSION",
+                meta.version.as_deref().unwrap_or_default(),
+            )
+            .env("DROP_DOWNLOAD_TYPE", format!("{:?}", meta.download_type));
+    }
+    if let Some(install_dir) = context.install_dir {
+        command.env("DROP_INSTALL_DIR", install_dir);
+    }
+    if let Some(error) = &context.error {
+        command.env("DROP_ERROR", error);
+    }
+
+    let mut child = match command.spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            warn!("failed to run {event:?} hook {program}: {e}");
+            return;
+        }
+    };
+    spawn(move || match child.wait() {
+        Ok(status) if status.success() => info!("{event:?} hook {program} finished"),
+        Ok(status) => warn!("{event:?} hook {program} exited with {status}"),
+        Err(e) => warn!("failed to wait on {event:?} hook {program}: {e}"),
+    });
+}
+
+fn notify(app_handle: &AppHandle, event: DownloadHookEvent, context: &HookContext) {
+    let name = context.meta.map(|meta| {
+        get_cached_object::<Game>(&meta.id)
+            .map(|game| game.name().to_owned())
+            .unwrap_or_else(|_| meta.id.clone())
+    });
+
+    let (title, body) = match event {
+        DownloadHookEvent::Completed => (
+            "Download complete",
+            format!("{} finished downloading", name.unwrap_or_default()),
+        ),
+        DownloadHookEvent::Error => (
+            "Download failed",
+            format!(
+                "{}: {}",
+                name.unwrap_or_default(),
+                context.error.as_deref().unwrap_or("unknown error")
+            ),
+        ),
+        DownloadHookEvent::QueueEmpty => (
+            "Downloads finished",
+            "Everything in the queue has downloaded".to_owned(),
+        ),
+    };
+
+    if let Err(e) = app_handle
+        .notification()
+        .builder()
+        .title(title)
+        .body(body)
+        .show()
+    {
+        warn!("failed to show {event:?} notification: {e}");
+    }
+}
@@ -1,4 +1,8 @@
+pub mod download_schedule;
 pub mod download_thread_control_flag;
+pub mod hooks;
 pub mod progress_object;
 pub mod queue;
+pub mod rate_limiter;
+pub mod retry;
 pub mod rolling_progress_updates;
@@ -47,6 +47,13 @@ impl ProgressHandle {
             .fetch_add(amount, std::sync::atomic::Ordering::AcqRel);
         calculate_update(&self.progress_object);
     }
+    pub fn revert(&self, amount: usize) {
+        let _ = self
+            .progress
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |current| {
+                Some(current.saturating_sub(amount))
+            });
+    }
     pub fn skip(&self, amount: usize) {
         self.progress
             .fetch_add(amount, std::sync::atomic::Ordering::Acquire);
@@ -110,6 +117,10 @@ impl ProgressObject {
     pub fn get(&self, index: usize) -> Arc<AtomicUsize> {
         self.progress_instances.lock().unwrap()[index].clone()
     }
+    /// In kB/s, 0 until the first update
+    pub fn get_speed(&self) -> usize {
+        self.rolling.get_average()
+    }
     fn update_window(&self, kilobytes_per_second: usize) {
         self.rolling.update(kilobytes_per_second);
     }
@@ -29,9 +29,6 @@ impl Queue {
     pub fn edit(&self) -> MutexGuard<'_, VecDeque<DownloadableMetadata>> {
         self.inner.lock().unwrap()
     }
-    pub fn pop_front(&self) -> Option<DownloadableMetadata> {
-        self.edit().pop_front()
-    }
     pub fn exists(&self, meta: DownloadableMetadata) -> bool {
         self.read().contains(&meta)
     }
@@ -0,0 +1,117 @@
+use std::{
+    sync::{LazyLock, Mutex},
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+/// Shared by every bucket thread, so the limit applies to the download
+/// manager as a whole rather than per-thread
+pub static DOWNLOAD_RATE_LIMITER: LazyLock<RateLimiter> = LazyLock::new(RateLimiter::new);
+
+/// Token bucket over bytes. Tokens are allowed to go negative, so
+/// a read larger than the bucket still gets through, it just
+/// waits off the debt afterwards.
+pub struct RateLimiter {
+    inner: Mutex<TokenBucket>,
+}
+
+struct TokenBucket {
+    // Bytes per second, 0 is unlimited
+    rate: usize,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.last_refill = now;
+        // Allow at most a second of burst
+        self.tokens = (self.tokens + elapsed * self.rate as f64).min(self.rate as f64);
+    }
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        Self {
+            inner: Mutex::new(TokenBucket {
+                rate: 0,
+                tokens: 0.0,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    /// Takes kB/s, to match the settings
+    pub fn set_limit(&self, kilobytes_per_second: usize) {
+        let mut bucket = self.inner.lock().unwrap();
+        bucket.rate = kilobytes_per_second * 1000;
+        bucket.tokens = bucket.rate as f64;
+        bucket.last_refill = Instant::now();
+    }
+
+    /// Blocks until `amount` bytes may be read
+    pub fn acquire(&self, amount: usize) {
+        let wait = {
+            let mut bucket = self.inner.lock().unwrap();
+            if bucket.rate == 0 {
+                return;
+            }
+            bucket.refill();
+            bucket.tokens -= amount as f64;
+            if bucket.tokens >= 0.0 {
+                return;
+            }
+            Duration::from_secs_f64(-bucket.tokens / bucket.rate as f64)
+        };
+
+        sleep(wait);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unlimited_never_waits() {
+        let limiter = RateLimiter::new();
+        let start = Instant::now();
+        limiter.acquire(usize::MAX);
+        assert!(start.elapsed() < Duration::from_millis(100));
+    }
+
+    #[test]
+    fn refill_allows_one_second_of_burst() {
+        let mut bucket = TokenBucket {
+            rate: 1000,
+            tokens: 0.0,
+            last_refill: Instant::now() - Duration::from_secs(5),
+        };
+        bucket.refill();
+        assert_eq!(bucket.tokens, 1000.0);
+    }
+
+    #[test]
+    fn waits_off_debt() {
+        let limiter = RateLimiter::new();
+        limiter.set_limit(1);
+
+        // A full bucket goes through straight away
+        let start = Instant::now();
+        limiter.acquire(1000);
+        assert!(start.elapsed() < Duration::from_millis(100));
+
+        // Anything after that has to wait for the bucket to refill
+        let start = Instant::now();
+        limiter.acquire(300);
+        assert!(start.elapsed() >= Duration::from_millis(250));
+    }
+}
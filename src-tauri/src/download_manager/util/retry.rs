@@ -0,0 +1,74 @@
+use std::{
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use crate::database::models::data::RetryPolicy;
+
+use super::download_thread_control_flag::{DownloadThreadControl, DownloadThreadControlFlag};
+
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+impl RetryPolicy {
+    /// Exponential, with jitter so that every thread that failed
+    /// at once doesn't retry at once as well
+    pub fn backoff(&self, attempt: usize) -> Duration {
+        let exponential = self
+            .initial_backoff
+            .saturating_mul(1 << attempt.min(32))
+            .min(self.max_backoff);
+        let jitter = rand::random_range(0..=exponential / 2);
+
+        Duration::from_millis(exponential / 2 + jitter)
+    }
+}
+
+/// Returns false if the download was stopped while waiting
+pub fn wait_for_retry(duration: Duration, control_flag: &DownloadThreadControl) -> bool {
+    let deadline = Instant::now() + duration;
+    loop {
+        if control_flag.get() == DownloadThreadControlFlag::Stop {
+            return false;
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return true;
+        }
+        sleep(STOP_POLL_INTERVAL.min(deadline - now));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 5,
+            initial_backoff: 1000,
+            max_backoff: 60 * 1000,
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_within_jitter() {
+        let policy = policy();
+        for attempt in 0..6 {
+            let exponential = 1000 << attempt;
+            let backoff = policy.backoff(attempt).as_millis() as u64;
+            assert!(
+                (exponential / 2..=exponential).contains(&backoff),
+                "attempt {attempt} waited {backoff}ms"
+            );
+        }
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        let policy = policy();
+        for attempt in [6, 32, 64, usize::MAX] {
+            let backoff = policy.backoff(attempt).as_millis() as u64;
+            assert!((30 * 1000..=60 * 1000).contains(&backoff));
+        }
+    }
+}
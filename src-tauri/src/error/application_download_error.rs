@@ -1,10 +1,11 @@
 use std::{
     fmt::{Display, Formatter},
-    io, sync::Arc,
+    io,
+    sync::Arc,
 };
 
+use humansize::{BINARY, format_size};
 use serde_with::SerializeDisplay;
-use humansize::{format_size, BINARY};
 
 use super::remote_access_error::RemoteAccessError;
 
@@ -14,17 +15,22 @@ pub enum ApplicationDownloadError {
     NotInitialized,
     Communication(RemoteAccessError),
     DiskFull(u64, u64),
-    #[allow(dead_code)]
     Checksum,
     Lock,
     IoError(Arc<io::Error>),
     DownloadError,
+    NotInstalled(String),
+    InvalidMove(String),
+    InvalidInstallDir(usize),
+    InvalidTool(String, String),
 }
 
 impl Display for ApplicationDownloadError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
-            ApplicationDownloadError::NotInitialized => write!(f, "Download not initalized, did something go wrong?"),
+            ApplicationDownloadError::NotInitialized => {
+                write!(f, "Download not initalized, did something go wrong?")
+            }
             ApplicationDownloadError::DiskFull(required, available) => write!(
                 f,
                 "Game requires {}, {} remaining left on disk.",
@@ -44,6 +50,50 @@ impl Display for ApplicationDownloadError {
                 f,
                 "Download failed. See Download Manager status for specific error"
             ),
+            ApplicationDownloadError::NotInstalled(id) => {
+                write!(f, "game {id} needs to be installed first")
+            }
+            ApplicationDownloadError::InvalidMove(reason) => {
+                write!(f, "can't move game: {reason}")
+            }
+            ApplicationDownloadError::InvalidInstallDir(index) => {
+                write!(f, "install directory {index} doesn't exist")
+            }
+            ApplicationDownloadError::InvalidTool(name, version) => {
+                write!(f, "invalid tool name or version: {name} {version}")
+            }
         }
     }
 }
+
+impl ApplicationDownloadError {
+    /// The server couldn't be reached, or went away mid-transfer
+    pub fn is_network_error(&self) -> bool {
+        match self {
+            ApplicationDownloadError::Communication(error) => error.is_transient(),
+            // Failing to read a response body surfaces as an io error
+            ApplicationDownloadError::IoError(error) => {
+                matches!(
+                    error.kind(),
+                    io::ErrorKind::TimedOut
+                        | io::ErrorKind::ConnectionReset
+                        | io::ErrorKind::ConnectionAborted
+                ) || error
+                    .get_ref()
+                    .is_some_and(|inner| inner.is::<reqwest::Error>())
+            }
+            _ => false,
+        }
+    }
+
+    /// Worth sending the same request again
+    pub fn is_transient(&self) -> bool {
+        self.is_network_error()
+            || matches!(
+                self,
+                ApplicationDownloadError::Checksum
+                    | ApplicationDownloadError::Lock
+                    | ApplicationDownloadError::DownloadError
+            )
+    }
+}
@@ -1,10 +1,13 @@
-use std::fmt::Display;
+use std::{fmt::Display, io};
 
 use serde_with::SerializeDisplay;
 
 #[derive(SerializeDisplay)]
 pub enum LibraryError {
     MetaNotFound(String),
+    DlcNotFound(String),
+    ModNotFound(String),
+    ModDeployFailed(io::Error),
 }
 impl Display for LibraryError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -13,6 +16,15 @@ impl Display for LibraryError {
                 f,
                 "Could not locate any installed version of game ID {id} in the database"
             ),
+            LibraryError::DlcNotFound(id) => {
+                write!(f, "DLC {id} isn't installed")
+            }
+            LibraryError::ModNotFound(id) => {
+                write!(f, "Mod {id} isn't installed")
+            }
+            LibraryError::ModDeployFailed(error) => {
+                write!(f, "Failed to apply mods: {error}")
+            }
         }
     }
 }
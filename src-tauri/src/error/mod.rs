@@ -2,5 +2,6 @@ pub mod application_download_error;
 pub mod download_manager_error;
 pub mod drop_server_error;
 pub mod library_error;
+pub mod package_error;
 pub mod process_error;
 pub mod remote_access_error;
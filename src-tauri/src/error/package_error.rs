@@ -0,0 +1,57 @@
+use std::{
+    fmt::{Display, Formatter},
+    io,
+    path::PathBuf,
+};
+
+use serde_with::SerializeDisplay;
+
+use super::application_download_error::ApplicationDownloadError;
+
+#[derive(Debug, SerializeDisplay)]
+pub enum PackageError {
+    NotInstalled(String),
+    AlreadyInstalled(String),
+    MissingVersion(String),
+    Manifest(ApplicationDownloadError),
+    InvalidInstallDir(usize),
+    TargetExists(PathBuf),
+    InvalidPackage(String),
+    ValidationFailed(usize),
+    IoError(io::Error),
+}
+
+impl Display for PackageError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PackageError::NotInstalled(id) => write!(f, "game {id} isn't installed"),
+            PackageError::AlreadyInstalled(id) => {
+                write!(f, "game {id} is already installed, uninstall it first")
+            }
+            PackageError::MissingVersion(id) => {
+                write!(f, "no version information stored for game {id}")
+            }
+            PackageError::Manifest(error) => write!(f, "failed to get manifest: {error}"),
+            PackageError::InvalidInstallDir(index) => {
+                write!(f, "install directory {index} doesn't exist")
+            }
+            PackageError::TargetExists(path) => write!(
+                f,
+                "{} already exists, move or delete it first",
+                path.display()
+            ),
+            PackageError::InvalidPackage(reason) => write!(f, "invalid game package: {reason}"),
+            PackageError::ValidationFailed(count) => write!(
+                f,
+                "{count} chunks in the package don't match the manifest, it might be corrupted"
+            ),
+            PackageError::IoError(error) => write!(f, "io error: {error}"),
+        }
+    }
+}
+
+impl From<io::Error> for PackageError {
+    fn from(value: io::Error) -> Self {
+        PackageError::IoError(value)
+    }
+}
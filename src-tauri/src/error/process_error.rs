@@ -11,7 +11,10 @@ pub enum ProcessError {
     IOError(Error),
     FormatError(String), // String errors supremacy
     InvalidPlatform,
-    OpenerError(tauri_plugin_opener::Error)
+    Moving,
+    ToolNotInstalled(String, String),
+    OpenerError(tauri_plugin_opener::Error),
+    ToolInUse(String, String, Vec<String>),
 }
 
 impl Display for ProcessError {
@@ -23,9 +26,17 @@ impl Display for ProcessError {
             ProcessError::InvalidVersion => "Invalid game version",
             ProcessError::IOError(error) => &error.to_string(),
             ProcessError::InvalidPlatform => "This game cannot be played on the current platform",
+            ProcessError::Moving => "Game is being moved to another install directory",
+            ProcessError::ToolNotInstalled(name, version) => {
+                &format!("This game needs {name} {version}, which isn't installed")
+            }
             ProcessError::FormatError(e) => &format!("Failed to format template: {e}"),
             ProcessError::OpenerError(error) => &format!("Failed to open directory: {error}"),
-                    };
+            ProcessError::ToolInUse(name, version, game_ids) => &format!(
+                "{name} {version} is still used by {}, update or uninstall them first",
+                game_ids.join(", ")
+            ),
+        };
         write!(f, "{s}")
     }
 }
@@ -85,6 +85,34 @@ impl Display for RemoteAccessError {
     }
 }
 
+impl RemoteAccessError {
+    /// Likely to succeed if tried again later, e.g. while the server restarts
+    pub fn is_transient(&self) -> bool {
+        match self {
+            RemoteAccessError::FetchError(error) => {
+                error.is_connect()
+                    || error.is_timeout()
+                    || error.is_request()
+                    || error.is_body()
+                    || error
+                        .status()
+                        .is_some_and(|status| is_transient_status(status.as_u16()))
+            }
+            RemoteAccessError::InvalidResponse(error) => {
+                is_transient_status(error.status_code as u16)
+            }
+            RemoteAccessError::ManifestDownloadFailed(status, _) => {
+                is_transient_status(status.as_u16())
+            }
+            _ => false,
+        }
+    }
+}
+
+fn is_transient_status(status: u16) -> bool {
+    matches!(status, 408 | 425 | 429 | 500 | 502 | 503 | 504)
+}
+
 impl From<reqwest::Error> for RemoteAccessError {
     fn from(err: reqwest::Error) -> Self {
         RemoteAccessError::FetchError(Arc::new(err))
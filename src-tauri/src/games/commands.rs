@@ -1,4 +1,4 @@
-use std::sync::Mutex;
+use std::{collections::HashMap, path::PathBuf, sync::Mutex};
 
 use tauri::AppHandle;
 
@@ -6,13 +6,21 @@ use crate::{
     AppState,
     database::{
         db::borrow_db_checked,
-        models::data::GameVersion,
+        models::data::{GameVersion, InstalledDlc, InstalledMod},
     },
-    error::{library_error::LibraryError, remote_access_error::RemoteAccessError},
+    error::{
+        library_error::LibraryError, package_error::PackageError,
+        remote_access_error::RemoteAccessError,
+    },
+    games::dlc::{installed_dlc, set_dlc_enabled_logic, uninstall_dlc_logic},
     games::library::{
         fetch_game_logic_offline, fetch_library_logic_offline, get_current_meta,
         uninstall_game_logic,
     },
+    games::mods::{
+        installed_mods, set_mod_enabled_logic, set_mod_load_order_logic, uninstall_mod_logic,
+    },
+    games::package::{export_game_logic, import_game_logic},
     offline,
 };
 
@@ -33,7 +41,8 @@ pub async fn fetch_library(
         fetch_library_logic,
         fetch_library_logic_offline,
         state
-    ).await
+    )
+    .await
 }
 
 #[tauri::command]
@@ -47,7 +56,8 @@ pub async fn fetch_game(
         fetch_game_logic_offline,
         game_id,
         state
-    ).await
+    )
+    .await
 }
 
 #[tauri::command]
@@ -74,3 +84,76 @@ pub async fn fetch_game_version_options(
 ) -> Result<Vec<GameVersion>, RemoteAccessError> {
     fetch_game_version_options_logic(game_id, state).await
 }
+
+#[tauri::command]
+pub async fn export_game(game_id: String, path: PathBuf) -> Result<(), PackageError> {
+    export_game_logic(game_id, path).await
+}
+
+#[tauri::command]
+pub async fn import_game(
+    path: PathBuf,
+    install_dir: usize,
+    app_handle: AppHandle,
+) -> Result<(), PackageError> {
+    import_game_logic(path, install_dir, app_handle).await
+}
+
+#[tauri::command]
+pub fn fetch_installed_dlc(game_id: String) -> HashMap<String, InstalledDlc> {
+    let db_handle = borrow_db_checked();
+    installed_dlc(&db_handle, &game_id)
+}
+
+#[tauri::command]
+pub fn set_dlc_enabled(
+    game_id: String,
+    dlc_id: String,
+    enabled: bool,
+    app_handle: AppHandle,
+) -> Result<(), LibraryError> {
+    set_dlc_enabled_logic(game_id, dlc_id, enabled, &app_handle)
+}
+
+#[tauri::command]
+pub fn uninstall_dlc(
+    game_id: String,
+    dlc_id: String,
+    app_handle: AppHandle,
+) -> Result<(), LibraryError> {
+    uninstall_dlc_logic(game_id, dlc_id, &app_handle)
+}
+
+#[tauri::command]
+pub fn fetch_installed_mods(game_id: String) -> Vec<InstalledMod> {
+    let db_handle = borrow_db_checked();
+    installed_mods(&db_handle, &game_id)
+}
+
+#[tauri::command]
+pub fn set_mod_enabled(
+    game_id: String,
+    mod_id: String,
+    enabled: bool,
+    app_handle: AppHandle,
+) -> Result<(), LibraryError> {
+    set_mod_enabled_logic(game_id, mod_id, enabled, &app_handle)
+}
+
+#[tauri::command]
+pub fn set_mod_load_order(
+    game_id: String,
+    mod_ids: Vec<String>,
+    app_handle: AppHandle,
+) -> Result<(), LibraryError> {
+    set_mod_load_order_logic(game_id, mod_ids, &app_handle)
+}
+
+#[tauri::command]
+pub fn uninstall_mod(
+    game_id: String,
+    mod_id: String,
+    app_handle: AppHandle,
+) -> Result<(), LibraryError> {
+    uninstall_mod_logic(game_id, mod_id, &app_handle)
+}
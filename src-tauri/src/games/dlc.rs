@@ -0,0 +1,148 @@
+use std::{
+    collections::HashMap,
+    fs::remove_dir_all,
+    path::{Path, PathBuf},
+};
+
+use log::{debug, warn};
+use serde::Serialize;
+use tauri::{AppHandle, Emitter};
+
+use crate::{
+    database::{
+        db::{borrow_db_checked, borrow_db_mut_checked},
+        models::data::{Database, GameDownloadStatus, InstalledDlc},
+    },
+    error::library_error::LibraryError,
+};
+
+/// DLC installs under here, inside its parent game's install directory
+pub static DLC_DIR: &str = "dlc";
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DlcUpdateEvent {
+    pub game_id: String,
+    pub dlc: HashMap<String, InstalledDlc>,
+}
+
+pub fn dlc_install_dir(parent_install_dir: &Path, dlc_id: &str) -> PathBuf {
+    parent_install_dir.join(DLC_DIR).join(dlc_id)
+}
+
+/// Where the parent game lives, if it's installed
+pub fn parent_install_dir(db: &Database, parent_id: &str) -> Option<PathBuf> {
+    match db.applications.game_statuses.get(parent_id)? {
+        GameDownloadStatus::Installed { install_dir, .. }
+        | GameDownloadStatus::SetupRequired { install_dir, .. } => Some(PathBuf::from(install_dir)),
+        _ => None,
+    }
+}
+
+pub fn installed_dlc(db: &Database, parent_id: &str) -> HashMap<String, InstalledDlc> {
+    db.applications
+        .installed_dlc
+        .get(parent_id)
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// Install directories of every enabled DLC, for the game to pick up on launch
+pub fn enabled_dlc_dirs(db: &Database, parent_id: &str) -> Vec<PathBuf> {
+    let Some(parent_dir) = parent_install_dir(db, parent_id) else {
+        return Vec::new();
+    };
+    installed_dlc(db, parent_id)
+        .into_iter()
+        .filter(|(_, dlc)| dlc.enabled)
+        .map(|(dlc_id, _)| dlc_install_dir(&parent_dir, &dlc_id))
+        .collect()
+}
+
+pub fn push_dlc_update(app_handle: &AppHandle, db: &Database, parent_id: &str) {
+    app_handle
+        .emit(
+            &format!("update_dlc/{parent_id}"),
+            DlcUpdateEvent {
+                game_id: parent_id.to_owned(),
+                dlc: installed_dlc(db, parent_id),
+            },
+        )
+        .unwrap();
+}
+
+/// Newly installed DLC starts enabled, updated DLC keeps its setting
+pub fn set_dlc_installed(
+    parent_id: &str,
+    dlc_id: &str,
+    version_name: String,
+    app_handle: &AppHandle,
+) {
+    let mut db_lock = borrow_db_mut_checked();
+    let installed = db_lock
+        .applications
+        .installed_dlc
+        .entry(parent_id.to_owned())
+        .or_default();
+    let enabled = installed.get(dlc_id).is_none_or(|dlc| dlc.enabled);
+    installed.insert(
+        dlc_id.to_owned(),
+        InstalledDlc {
+            version_name,
+            enabled,
+        },
+    );
+    push_dlc_update(app_handle, &db_lock, parent_id);
+}
+
+pub fn set_dlc_enabled_logic(
+    parent_id: String,
+    dlc_id: String,
+    enabled: bool,
+    app_handle: &AppHandle,
+) -> Result<(), LibraryError> {
+    let mut db_lock = borrow_db_mut_checked();
+    let dlc = db_lock
+        .applications
+        .installed_dlc
+        .get_mut(&parent_id)
+        .and_then(|installed| installed.get_mut(&dlc_id))
+        .ok_or(LibraryError::DlcNotFound(dlc_id))?;
+    dlc.enabled = enabled;
+    push_dlc_update(app_handle, &db_lock, &parent_id);
+
+    Ok(())
+}
+
+pub fn uninstall_dlc_logic(
+    parent_id: String,
+    dlc_id: String,
+    app_handle: &AppHandle,
+) -> Result<(), LibraryError> {
+    let install_dir = {
+        let db_lock = borrow_db_checked();
+        if !installed_dlc(&db_lock, &parent_id).contains_key(&dlc_id) {
+            return Err(LibraryError::DlcNotFound(dlc_id));
+        }
+        parent_install_dir(&db_lock, &parent_id).map(|dir| dlc_install_dir(&dir, &dlc_id))
+    };
+
+    if let Some(install_dir) = install_dir
+        && install_dir.exists()
+        && let Err(e) = remove_dir_all(&install_dir)
+    {
+        warn!(
+            "failed to remove DLC {dlc_id} at {}: {e}",
+            install_dir.display()
+        );
+    }
+
+    let mut db_lock = borrow_db_mut_checked();
+    if let Some(installed) = db_lock.applications.installed_dlc.get_mut(&parent_id) {
+        installed.remove(&dlc_id);
+    }
+    push_dlc_update(app_handle, &db_lock, &parent_id);
+
+    debug!("uninstalled DLC {dlc_id} of {parent_id}");
+    Ok(())
+}
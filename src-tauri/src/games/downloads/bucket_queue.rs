@@ -0,0 +1,117 @@
+use std::{collections::VecDeque, sync::Mutex};
+
+use crate::{
+    database::models::data::Settings,
+    games::downloads::manifest::{DownloadBucket, DownloadDrop},
+};
+
+/// Used until we've measured how fast the link actually is
+pub const INITIAL_BUCKET_SIZE: usize = 63 * 1000 * 1000;
+
+/// Roughly how long a single chunk request should take. Long enough that
+/// per-request overhead disappears, short enough that threads finish together.
+const TARGET_REQUEST_SECONDS: usize = 10;
+
+#[derive(Clone, Copy)]
+pub struct BucketSizer {
+    min: usize,
+    max: usize,
+}
+
+impl BucketSizer {
+    pub fn new(settings: &Settings) -> Self {
+        let min = settings.min_bucket_size.max(1);
+        Self {
+            min,
+            max: settings.max_bucket_size.max(min),
+        }
+    }
+
+    /// `kilobytes_per_second` is across every thread, and 0 if nothing
+    /// has been measured yet
+    pub fn bucket_size(&self, kilobytes_per_second: usize, threads: usize) -> usize {
+        if kilobytes_per_second == 0 {
+            return INITIAL_BUCKET_SIZE.clamp(self.min, self.max);
+        }
+
+        let per_thread = kilobytes_per_second * 1000 / threads.max(1);
+        (per_thread * TARGET_REQUEST_SECONDS).clamp(self.min, self.max)
+    }
+
+    /// Never more threads than there are buckets worth of work left,
+    /// so small downloads don't pay for idle threads. 0 if there's nothing left.
+    pub fn thread_count(
+        &self,
+        remaining: &BucketQueue,
+        kilobytes_per_second: usize,
+        max_threads: usize,
+    ) -> usize {
+        if remaining.is_empty() {
+            return 0;
+        }
+
+        let max_threads = max_threads.max(1);
+        let bucket_size = self.bucket_size(kilobytes_per_second, max_threads);
+        remaining
+            .remaining_bytes()
+            .div_ceil(bucket_size)
+            .min(remaining.len())
+            .clamp(1, max_threads)
+    }
+}
+
+/// Drops waiting to be downloaded. Threads take buckets off the front
+/// sized for the current throughput, rather than working through a
+/// list of buckets fixed up front.
+pub struct BucketQueue {
+    game_id: String,
+    version: String,
+    drops: Mutex<VecDeque<DownloadDrop>>,
+}
+
+impl BucketQueue {
+    pub fn new(game_id: String, version: String, drops: Vec<DownloadDrop>) -> Self {
+        Self {
+            game_id,
+            version,
+            drops: Mutex::new(drops.into()),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.drops.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.drops.lock().unwrap().is_empty()
+    }
+
+    pub fn remaining_bytes(&self) -> usize {
+        self.drops.lock().unwrap().iter().map(|e| e.length).sum()
+    }
+
+    pub fn push(&self, drop: DownloadDrop) {
+        self.drops.lock().unwrap().push_back(drop);
+    }
+
+    /// Always takes at least one drop, so drops bigger than `size`
+    /// get a bucket of their own
+    pub fn next_bucket(&self, size: usize) -> Option<DownloadBucket> {
+        let mut queue = self.drops.lock().unwrap();
+
+        let mut drops = vec![queue.pop_front()?];
+        let mut bucket_size = drops[0].length;
+        while let Some(next) = queue.front()
+            && bucket_size + next.length <= size
+        {
+            bucket_size += next.length;
+            drops.push(queue.pop_front().unwrap());
+        }
+
+        Some(DownloadBucket {
+            game_id: self.game_id.clone(),
+            version: self.version.clone(),
+            drops,
+        })
+    }
+}
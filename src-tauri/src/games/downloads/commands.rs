@@ -1,20 +1,25 @@
 use std::{
+    collections::HashMap,
     path::PathBuf,
     sync::{Arc, Mutex},
 };
 
-
 use crate::{
     AppState,
-    database::{
-        db::borrow_db_checked,
-        models::data::GameDownloadStatus,
-    },
+    database::{db::borrow_db_checked, models::data::GameDownloadStatus},
     download_manager::downloadable::Downloadable,
     error::application_download_error::ApplicationDownloadError,
+    games::dlc::{dlc_install_dir, installed_dlc, parent_install_dir},
 };
 
-use super::download_agent::GameDownloadAgent;
+use super::{
+    download_agent::GameDownloadAgent,
+    drop_data::DropData,
+    manifest::get_installed_manifest,
+    move_agent::GameMoveAgent,
+    plan::{DownloadPlan, plan_game_download},
+    stale::{find_stale_files, installed_game, remove_stale_files},
+};
 
 #[tauri::command]
 pub async fn download_game(
@@ -25,8 +30,13 @@ pub async fn download_game(
 ) -> Result<(), ApplicationDownloadError> {
     let sender = { state.lock().unwrap().download_manager.get_sender().clone() };
 
-    let game_download_agent =
-        GameDownloadAgent::new_from_index(game_id.clone(), game_version.clone(), install_dir, sender).await?;
+    let game_download_agent = GameDownloadAgent::new_from_index(
+        game_id.clone(),
+        game_version.clone(),
+        install_dir,
+        sender,
+    )
+    .await?;
 
     let game_download_agent =
         Arc::new(Box::new(game_download_agent) as Box<dyn Downloadable + Send + Sync>);
@@ -40,11 +50,88 @@ pub async fn download_game(
     Ok(())
 }
 
+#[tauri::command]
+pub async fn update_game(
+    game_id: String,
+    game_version: String,
+    dlc_versions: Option<HashMap<String, String>>,
+    state: tauri::State<'_, Mutex<AppState<'_>>>,
+) -> Result<(), ApplicationDownloadError> {
+    let sender = { state.lock().unwrap().download_manager.get_sender().clone() };
+
+    let game_download_agent =
+        GameDownloadAgent::new_update(game_id.clone(), game_version, sender.clone()).await?;
+    let mut download_agents = vec![Arc::new(
+        Box::new(game_download_agent) as Box<dyn Downloadable + Send + Sync>
+    )];
+
+    // Installed DLC gets updated alongside its parent, queued after it.
+    // Everything is set up before anything is queued, so a failure
+    // doesn't leave the game updating without its DLC
+    let installed = installed_dlc(&borrow_db_checked(), &game_id);
+    for (dlc_id, dlc_version) in dlc_versions.unwrap_or_default() {
+        let Some(current) = installed.get(&dlc_id) else {
+            continue;
+        };
+        if current.version_name == dlc_version {
+            continue;
+        }
+
+        let dlc_download_agent =
+            GameDownloadAgent::new_dlc(game_id.clone(), dlc_id, dlc_version, sender.clone())
+                .await?;
+        download_agents.push(Arc::new(
+            Box::new(dlc_download_agent) as Box<dyn Downloadable + Send + Sync>
+        ));
+    }
+
+    let state_lock = state.lock().unwrap();
+    for download_agent in download_agents {
+        state_lock
+            .download_manager
+            .queue_download(download_agent)
+            .unwrap();
+    }
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn download_dlc(
+    game_id: String,
+    dlc_id: String,
+    dlc_version: String,
+    state: tauri::State<'_, Mutex<AppState<'_>>>,
+) -> Result<(), ApplicationDownloadError> {
+    let sender = { state.lock().unwrap().download_manager.get_sender().clone() };
+
+    let dlc_download_agent =
+        GameDownloadAgent::new_dlc(game_id, dlc_id, dlc_version, sender).await?;
+
+    let dlc_download_agent =
+        Arc::new(Box::new(dlc_download_agent) as Box<dyn Downloadable + Send + Sync>);
+    state
+        .lock()
+        .unwrap()
+        .download_manager
+        .queue_download(dlc_download_agent)
+        .unwrap();
+
+    Ok(())
+}
+
+/// DLC isn't tracked until it's complete, so pass its parent to pick
+/// a cancelled DLC download back up from its .dropdata
 #[tauri::command]
 pub async fn resume_download(
     game_id: String,
+    parent_id: Option<String>,
     state: tauri::State<'_, Mutex<AppState<'_>>>,
 ) -> Result<(), ApplicationDownloadError> {
+    if let Some(parent_id) = parent_id {
+        return resume_dlc_download(parent_id, game_id, state).await;
+    }
+
     let s = borrow_db_checked()
         .applications
         .game_statuses
@@ -83,3 +170,154 @@ pub async fn resume_download(
         .unwrap();
     Ok(())
 }
+
+async fn resume_dlc_download(
+    parent_id: String,
+    dlc_id: String,
+    state: tauri::State<'_, Mutex<AppState<'_>>>,
+) -> Result<(), ApplicationDownloadError> {
+    let parent_dir = parent_install_dir(&borrow_db_checked(), &parent_id)
+        .ok_or_else(|| ApplicationDownloadError::NotInstalled(parent_id.clone()))?;
+    let dlc_version = DropData::read(&dlc_install_dir(&parent_dir, &dlc_id))
+        .map_err(|e| ApplicationDownloadError::IoError(Arc::new(e)))?
+        .game_version;
+
+    let sender = { state.lock().unwrap().download_manager.get_sender().clone() };
+
+    let dlc_download_agent =
+        GameDownloadAgent::new_dlc(parent_id, dlc_id, dlc_version, sender).await?;
+
+    let dlc_download_agent =
+        Arc::new(Box::new(dlc_download_agent) as Box<dyn Downloadable + Send + Sync>);
+    state
+        .lock()
+        .unwrap()
+        .download_manager
+        .queue_download(dlc_download_agent)
+        .unwrap();
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn repair_game(
+    game_id: String,
+    state: tauri::State<'_, Mutex<AppState<'_>>>,
+) -> Result<(), ApplicationDownloadError> {
+    let sender = { state.lock().unwrap().download_manager.get_sender().clone() };
+
+    let game_download_agent = GameDownloadAgent::new_repair(game_id, sender).await?;
+
+    let game_download_agent =
+        Arc::new(Box::new(game_download_agent) as Box<dyn Downloadable + Send + Sync>);
+    state
+        .lock()
+        .unwrap()
+        .download_manager
+        .queue_download(game_download_agent)
+        .unwrap();
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn move_game(
+    game_id: String,
+    install_dir: usize,
+    state: tauri::State<'_, Mutex<AppState<'_>>>,
+) -> Result<(), ApplicationDownloadError> {
+    let sender = { state.lock().unwrap().download_manager.get_sender().clone() };
+
+    let game_move_agent = GameMoveAgent::new(game_id, install_dir, sender).await?;
+
+    let game_move_agent =
+        Arc::new(Box::new(game_move_agent) as Box<dyn Downloadable + Send + Sync>);
+    state
+        .lock()
+        .unwrap()
+        .download_manager
+        .queue_download(game_move_agent)
+        .unwrap();
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn download_mod(
+    game_id: String,
+    mod_id: String,
+    mod_version: String,
+    state: tauri::State<'_, Mutex<AppState<'_>>>,
+) -> Result<(), ApplicationDownloadError> {
+    let sender = { state.lock().unwrap().download_manager.get_sender().clone() };
+
+    let mod_download_agent =
+        GameDownloadAgent::new_mod(game_id, mod_id, mod_version, sender).await?;
+
+    let mod_download_agent =
+        Arc::new(Box::new(mod_download_agent) as Box<dyn Downloadable + Send + Sync>);
+    state
+        .lock()
+        .unwrap()
+        .download_manager
+        .queue_download(mod_download_agent)
+        .unwrap();
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn download_tool(
+    name: String,
+    version: String,
+    state: tauri::State<'_, Mutex<AppState<'_>>>,
+) -> Result<(), ApplicationDownloadError> {
+    let sender = { state.lock().unwrap().download_manager.get_sender().clone() };
+
+    let tool_download_agent = GameDownloadAgent::new_tool(name, version, sender).await?;
+
+    let tool_download_agent =
+        Arc::new(Box::new(tool_download_agent) as Box<dyn Downloadable + Send + Sync>);
+    state
+        .lock()
+        .unwrap()
+        .download_manager
+        .queue_download(tool_download_agent)
+        .unwrap();
+
+    Ok(())
+}
+
+/// Files in the install directory that the installed version doesn't ship
+#[tauri::command]
+pub async fn fetch_stale_files(game_id: String) -> Result<Vec<String>, ApplicationDownloadError> {
+    let (version, install_dir) = installed_game(&game_id)
+        .ok_or_else(|| ApplicationDownloadError::NotInstalled(game_id.clone()))?;
+    let manifest = get_installed_manifest(&game_id, &version, &install_dir).await?;
+
+    Ok(find_stale_files(&game_id, &install_dir, &manifest))
+}
+
+#[tauri::command]
+pub async fn delete_stale_files(
+    game_id: String,
+    files: Vec<String>,
+) -> Result<usize, ApplicationDownloadError> {
+    let (version, install_dir) = installed_game(&game_id)
+        .ok_or_else(|| ApplicationDownloadError::NotInstalled(game_id.clone()))?;
+    let manifest = get_installed_manifest(&game_id, &version, &install_dir).await?;
+
+    remove_stale_files(&game_id, &install_dir, &manifest, &files)
+        .map_err(|e| ApplicationDownloadError::IoError(Arc::new(e)))
+}
+
+/// What downloading a version would involve, without starting it.
+/// `install_dir` is only used if the game isn't installed yet.
+#[tauri::command]
+pub async fn plan_download(
+    game_id: String,
+    game_version: String,
+    install_dir: usize,
+) -> Result<DownloadPlan, ApplicationDownloadError> {
+    plan_game_download(game_id, game_version, install_dir).await
+}
@@ -0,0 +1,271 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, File, OpenOptions, create_dir_all},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::PathBuf,
+};
+
+#[cfg(unix)]
+use std::fs::{Permissions, set_permissions};
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+
+use crate::games::downloads::manifest::{
+    ChunkLocation, DownloadDrop, DropChunk, DropManifest, chunk_index,
+};
+
+/// Changed files are built in here, and only moved over the installed
+/// version once every chunk is on disk. Until then, the installed
+/// version stays playable.
+pub static DELTA_STAGING_DIR: &str = ".dropdelta";
+
+/// The installed version that an update is diffed against
+pub struct DeltaSource {
+    pub version: String,
+    pub manifest: DropManifest,
+    pub install_dir: PathBuf,
+}
+
+impl DeltaSource {
+    pub fn staging_dir(&self) -> PathBuf {
+        self.install_dir.join(DELTA_STAGING_DIR)
+    }
+
+    /// Where every chunk of the installed version lives on disk,
+    /// keyed by checksum
+    pub fn chunk_index(&self) -> HashMap<String, ChunkLocation> {
+        chunk_index(&self.manifest, &self.install_dir)
+    }
+
+    /// Files that are identical in both versions, and don't need to be touched
+    pub fn unchanged_files(&self, target: &DropManifest) -> HashSet<String> {
+        target
+            .iter()
+            .filter(|(raw_path, chunk)| {
+                self.manifest.get(*raw_path).is_some_and(|existing| {
+                    existing.checksums == chunk.checksums
+                        && existing.lengths == chunk.lengths
+                        && existing.permissions == chunk.permissions
+                        && existing.hash_algorithm == chunk.hash_algorithm
+                })
+            })
+            .map(|(raw_path, _)| raw_path.clone())
+            .collect()
+    }
+
+    /// Files that only exist in the installed version
+    pub fn removed_files(&self, target: &DropManifest) -> Vec<String> {
+        self.manifest
+            .keys()
+            .filter(|raw_path| !target.contains_key(*raw_path))
+            .cloned()
+            .collect()
+    }
+
+    /// Whether a changed file was already moved over the installed version,
+    /// by an apply that got interrupted before the download was marked done
+    pub fn already_applied(
+        &self,
+        raw_path: &str,
+        chunk: &DropChunk,
+        contexts: &HashMap<String, bool>,
+    ) -> bool {
+        !self.staging_dir().join(raw_path).exists()
+            && chunk
+                .checksums
+                .iter()
+                .all(|checksum| *contexts.get(checksum).unwrap_or(&false))
+    }
+
+    /// Moves every staged file over the installed version, and deletes
+    /// files the new version no longer ships
+    pub fn apply(&self, target: &DropManifest) -> io::Result<()> {
+        let staging_dir = self.staging_dir();
+        let unchanged = self.unchanged_files(target);
+
+        for raw_path in target
+            .keys()
+            .filter(|raw_path| !unchanged.contains(*raw_path))
+        {
+            let staged = staging_dir.join(raw_path);
+            // Already moved by a previous, interrupted attempt
+            if !staged.exists() {
+                continue;
+            }
+            let destination = self.install_dir.join(raw_path);
+            if let Some(container) = destination.parent() {
+                create_dir_all(container)?;
+            }
+            fs::rename(staged, destination)?;
+        }
+
+        for raw_path in self.removed_files(target) {
+            let path = self.install_dir.join(raw_path);
+            if path.exists() {
+                fs::remove_file(path)?;
+            }
+        }
+
+        if staging_dir.exists() {
+            fs::remove_dir_all(staging_dir)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Copies a chunk of the installed version into a drop of the new version
+pub fn copy_chunk(source: &ChunkLocation, drop: &DownloadDrop) -> io::Result<()> {
+    let mut source_file = File::open(&source.path)?;
+    source_file.seek(SeekFrom::Start(source.offset as u64))?;
+
+    let mut destination = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&drop.path)?;
+    destination.seek(SeekFrom::Start(drop.start as u64))?;
+
+    let copied = io::copy(
+        &mut source_file.take(source.length as u64),
+        &mut destination,
+    )?;
+    if copied as usize != drop.length {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!(
+                "expected to copy {} bytes from {}, got {}",
+                drop.length,
+                source.path.display(),
+                copied
+            ),
+        ));
+    }
+    destination.flush()?;
+
+    #[cfg(unix)]
+    set_permissions(&drop.path, Permissions::from_mode(drop.permissions))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::games::downloads::{hasher::HashAlgorithm, manifest::tests::chunk};
+
+    fn source(install_dir: PathBuf) -> DeltaSource {
+        DeltaSource {
+            version: "1.0".to_owned(),
+            manifest: HashMap::from([
+                ("game.exe".to_owned(), chunk(&["a", "b"], &[3, 4])),
+                ("data/level.pak".to_owned(), chunk(&["c"], &[5])),
+                ("old.dll".to_owned(), chunk(&["d"], &[2])),
+            ]),
+            install_dir,
+        }
+    }
+
+    fn download_drop(path: PathBuf, start: usize, length: usize) -> DownloadDrop {
+        DownloadDrop {
+            index: 0,
+            filename: "game.exe".to_owned(),
+            path,
+            start,
+            length,
+            checksum: "b".to_owned(),
+            permissions: 0o644,
+            resume_offset: 0,
+            hash_algorithm: HashAlgorithm::Md5,
+        }
+    }
+
+    #[test]
+    fn finds_unchanged_and_removed_files() {
+        let source = source(PathBuf::from("install"));
+        let target = HashMap::from([
+            ("game.exe".to_owned(), chunk(&["a", "b"], &[3, 4])),
+            ("data/level.pak".to_owned(), chunk(&["e"], &[5])),
+        ]);
+
+        assert_eq!(
+            source.unchanged_files(&target),
+            HashSet::from(["game.exe".to_owned()])
+        );
+        assert_eq!(source.removed_files(&target), vec!["old.dll".to_owned()]);
+    }
+
+    #[test]
+    fn indexes_chunks_by_offset() {
+        let source = source(PathBuf::from("install"));
+        let index = source.chunk_index();
+
+        let location = &index["b"];
+        assert_eq!(location.path, PathBuf::from("install").join("game.exe"));
+        assert_eq!(location.offset, 3);
+        assert_eq!(location.length, 4);
+        assert_eq!(index["c"].offset, 0);
+    }
+
+    #[test]
+    fn copies_installed_chunk_into_drop() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("game.exe"), b"abcdefg").unwrap();
+        let source = source(dir.path().to_path_buf());
+        let index = source.chunk_index();
+
+        let staged = dir.path().join("staged.exe");
+        fs::write(&staged, b"xx").unwrap();
+        copy_chunk(&index["b"], &download_drop(staged.clone(), 2, 4)).unwrap();
+
+        assert_eq!(fs::read(staged).unwrap(), b"xxdefg");
+    }
+
+    #[test]
+    fn resumes_interrupted_apply() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = source(dir.path().to_path_buf());
+        let target = HashMap::from([
+            ("game.exe".to_owned(), chunk(&["a", "e"], &[3, 4])),
+            ("data/level.pak".to_owned(), chunk(&["f"], &[5])),
+        ]);
+        let contexts = HashMap::from([
+            ("a".to_owned(), true),
+            ("e".to_owned(), true),
+            ("f".to_owned(), true),
+        ]);
+
+        fs::create_dir_all(dir.path().join("data")).unwrap();
+        fs::create_dir_all(source.staging_dir().join("data")).unwrap();
+        fs::write(dir.path().join("old.dll"), b"xx").unwrap();
+        fs::write(source.staging_dir().join("data/level.pak"), b"fffff").unwrap();
+        // The first attempt only got as far as moving game.exe
+        fs::write(dir.path().join("game.exe"), b"abcnewb").unwrap();
+
+        assert!(source.already_applied("game.exe", &target["game.exe"], &contexts));
+        assert!(!source.already_applied("data/level.pak", &target["data/level.pak"], &contexts));
+        assert!(!source.already_applied("game.exe", &target["game.exe"], &HashMap::new()));
+
+        source.apply(&target).unwrap();
+
+        assert_eq!(fs::read(dir.path().join("game.exe")).unwrap(), b"abcnewb");
+        assert_eq!(
+            fs::read(dir.path().join("data/level.pak")).unwrap(),
+            b"fffff"
+        );
+        assert!(!dir.path().join("old.dll").exists());
+        assert!(!source.staging_dir().exists());
+    }
+
+    #[test]
+    fn rejects_truncated_source() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("game.exe"), b"abcde").unwrap();
+        let source = source(dir.path().to_path_buf());
+        let index = source.chunk_index();
+
+        let staged = dir.path().join("staged.exe");
+        let error = copy_chunk(&index["b"], &download_drop(staged, 0, 4)).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -1,7 +1,8 @@
 use crate::auth::generate_authorization_header;
 use crate::database::db::{borrow_db_checked, borrow_db_mut_checked};
 use crate::database::models::data::{
-    ApplicationTransientStatus, DownloadType, DownloadableMetadata,
+    ApplicationTransientStatus, Database, DownloadType, DownloadableMetadata, GameDownloadStatus,
+    GameVersion, QueuedDownload, QueuedDownloadKind, RetryPolicy,
 };
 use crate::download_manager::download_manager_frontend::{DownloadManagerSignal, DownloadStatus};
 use crate::download_manager::downloadable::Downloadable;
@@ -9,22 +10,33 @@ use crate::download_manager::util::download_thread_control_flag::{
     DownloadThreadControl, DownloadThreadControlFlag,
 };
 use crate::download_manager::util::progress_object::{ProgressHandle, ProgressObject};
+use crate::download_manager::util::retry::wait_for_retry;
 use crate::error::application_download_error::ApplicationDownloadError;
 use crate::error::remote_access_error::RemoteAccessError;
+use crate::games::dlc::{dlc_install_dir, installed_dlc, parent_install_dir, set_dlc_installed};
+use crate::games::downloads::delta::{DELTA_STAGING_DIR, DeltaSource, copy_chunk};
 use crate::games::downloads::manifest::{
     DownloadBucket, DownloadContext, DownloadDrop, DropManifest, DropValidateContext, ManifestBody,
+    fetch_manifest, get_installed_manifest,
 };
+use crate::games::downloads::stale::reconcile_stale_files;
 use crate::games::downloads::validate::validate_game_chunk;
 use crate::games::library::{on_game_complete, push_game_update, set_partially_installed};
+use crate::games::mods::{
+    installed_mods, mod_owned_files, mod_store_dir, redeploy_mods, set_mod_installed, undeploy_mods,
+};
 use crate::games::state::GameStatusManager;
-use crate::process::utils::get_disk_available;
+use crate::process::tools::{set_tool_installed, tool_install_dir};
+use crate::process::utils::{get_disk_available, space_needed};
 use crate::remote::requests::generate_url;
-use crate::remote::utils::{DROP_CLIENT_ASYNC, DROP_CLIENT_SYNC};
+use crate::remote::utils::DROP_CLIENT_SYNC;
+use humansize::{BINARY, format_size};
 use log::{debug, error, info, warn};
 use rayon::ThreadPoolBuilder;
-use std::collections::HashMap;
-use std::fs::{OpenOptions, create_dir_all};
+use std::collections::{HashMap, HashSet};
+use std::fs::{OpenOptions, create_dir_all, remove_dir_all};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::mpsc::Sender;
 use std::sync::{Arc, Mutex};
 use std::time::Instant;
@@ -33,12 +45,9 @@ use tauri::{AppHandle, Emitter};
 #[cfg(target_os = "linux")]
 use rustix::fs::{FallocateFlags, fallocate};
 
+use super::bucket_queue::{BucketQueue, BucketSizer};
 use super::download_logic::download_game_bucket;
-use super::drop_data::DropData;
-
-static RETRY_COUNT: usize = 3;
-
-const TARGET_BUCKET_SIZE: usize = 63 * 1000 * 1000;
+use super::drop_data::{DropData, DropDataCheckpoint};
 
 pub struct GameDownloadAgent {
     pub id: String,
@@ -51,6 +60,20 @@ pub struct GameDownloadAgent {
     sender: Sender<DownloadManagerSignal>,
     pub dropdata: DropData,
     status: Mutex<DownloadStatus>,
+    delta: Option<DeltaSource>,
+    delta_applied: Mutex<bool>,
+    // Repairs check the installed files before downloading anything
+    repair: bool,
+    repair_checked: Mutex<bool>,
+    download_type: DownloadType,
+    // DLC and mods install into their parent game, and are tracked under it
+    parent: Option<String>,
+    // Contexts we hashed ourselves while downloading them
+    verified_contexts: Mutex<HashSet<String>>,
+    // Set by the download manager, 0 until then
+    thread_budget: AtomicUsize,
+    // In kB/s, as of the last run. Progress starts from scratch each run.
+    last_speed: AtomicUsize,
 }
 
 impl GameDownloadAgent {
@@ -74,16 +97,243 @@ impl GameDownloadAgent {
         base_dir: PathBuf,
         sender: Sender<DownloadManagerSignal>,
     ) -> Result<Self, ApplicationDownloadError> {
-        // Don't run by default
-        let control_flag = DownloadThreadControl::new(DownloadThreadControlFlag::Stop);
-
         let base_dir_path = Path::new(&base_dir);
         let data_base_dir_path = base_dir_path.join(id.clone());
 
         let stored_manifest =
             DropData::generate(id.clone(), version.clone(), data_base_dir_path.clone());
 
-        let result = Self {
+        let result = Self::create(id, version, stored_manifest, None, sender);
+
+        result.ensure_manifest_exists().await?;
+        result.check_disk_space()?;
+
+        Ok(result)
+    }
+
+    /// Updates an installed game in place, only downloading chunks
+    /// that don't already exist somewhere in the installed version
+    pub async fn new_update(
+        id: String,
+        version: String,
+        sender: Sender<DownloadManagerSignal>,
+    ) -> Result<Self, ApplicationDownloadError> {
+        let (installed_version, install_dir) = {
+            let db_lock = borrow_db_checked();
+            match db_lock.applications.game_statuses.get(&id) {
+                Some(
+                    GameDownloadStatus::Installed {
+                        version_name,
+                        install_dir,
+                    }
+                    | GameDownloadStatus::SetupRequired {
+                        version_name,
+                        install_dir,
+                    },
+                ) => (version_name.clone(), PathBuf::from(install_dir)),
+                _ => return Err(ApplicationDownloadError::NotInstalled(id)),
+            }
+        };
+
+        let previous_manifest =
+            get_installed_manifest(&id, &installed_version, &install_dir).await?;
+
+        let stored_manifest = match DropData::read(&install_dir) {
+            Ok(existing) if existing.game_version == version => existing,
+            _ => {
+                // Anything staged belongs to an update to a different version
+                let _ = remove_dir_all(install_dir.join(DELTA_STAGING_DIR));
+                DropData::new(id.clone(), version.clone(), install_dir.clone())
+            }
+        };
+
+        let delta = DeltaSource {
+            version: installed_version,
+            manifest: previous_manifest,
+            install_dir,
+        };
+
+        let result = Self::create(id, version, stored_manifest, Some(delta), sender);
+
+        result.ensure_manifest_exists().await?;
+        result.check_disk_space()?;
+
+        Ok(result)
+    }
+
+    /// Checks an installed game against its manifest, and redownloads
+    /// only the chunks that don't match. The game stays installed throughout.
+    pub async fn new_repair(
+        id: String,
+        sender: Sender<DownloadManagerSignal>,
+    ) -> Result<Self, ApplicationDownloadError> {
+        let (version, install_dir) = {
+            let db_lock = borrow_db_checked();
+            match db_lock.applications.game_statuses.get(&id) {
+                Some(
+                    GameDownloadStatus::Installed {
+                        version_name,
+                        install_dir,
+                    }
+                    | GameDownloadStatus::SetupRequired {
+                        version_name,
+                        install_dir,
+                    },
+                ) => (version_name.clone(), PathBuf::from(install_dir)),
+                _ => return Err(ApplicationDownloadError::NotInstalled(id)),
+            }
+        };
+
+        let manifest = get_installed_manifest(&id, &version, &install_dir).await?;
+
+        // Don't trust what the .dropdata says, that's what we're checking
+        let dropdata = DropData::new(id.clone(), version.clone(), install_dir);
+        dropdata.set_manifest(&manifest);
+
+        let mut result = Self::create(id, version, dropdata, None, sender);
+        result.repair = true;
+        *result.manifest.lock().unwrap() = Some(manifest);
+
+        Ok(result)
+    }
+
+    /// Installs DLC into its parent game's install directory, updating
+    /// it in place if another version is already installed
+    pub async fn new_dlc(
+        parent_id: String,
+        id: String,
+        version: String,
+        sender: Sender<DownloadManagerSignal>,
+    ) -> Result<Self, ApplicationDownloadError> {
+        let (parent_dir, installed_version) = {
+            let db_lock = borrow_db_checked();
+            let parent_dir = parent_install_dir(&db_lock, &parent_id)
+                .ok_or_else(|| ApplicationDownloadError::NotInstalled(parent_id.clone()))?;
+            let installed_version = installed_dlc(&db_lock, &parent_id)
+                .get(&id)
+                .map(|dlc| dlc.version_name.clone());
+            (parent_dir, installed_version)
+        };
+        let install_dir = dlc_install_dir(&parent_dir, &id);
+
+        Self::new_child(
+            parent_id,
+            id,
+            version,
+            DownloadType::Dlc,
+            install_dir,
+            installed_version,
+            sender,
+        )
+        .await
+    }
+
+    /// Downloads a mod into its game's mod store. It's copied over
+    /// the game's files once it's complete, and whenever it's enabled.
+    pub async fn new_mod(
+        parent_id: String,
+        id: String,
+        version: String,
+        sender: Sender<DownloadManagerSignal>,
+    ) -> Result<Self, ApplicationDownloadError> {
+        let (parent_dir, installed_version) = {
+            let db_lock = borrow_db_checked();
+            let parent_dir = parent_install_dir(&db_lock, &parent_id)
+                .ok_or_else(|| ApplicationDownloadError::NotInstalled(parent_id.clone()))?;
+            let installed_version = installed_mods(&db_lock, &parent_id)
+                .into_iter()
+                .find(|installed| installed.mod_id == id)
+                .map(|installed| installed.version_name);
+            (parent_dir, installed_version)
+        };
+        let install_dir = mod_store_dir(&parent_dir, &id);
+
+        Self::new_child(
+            parent_id,
+            id,
+            version,
+            DownloadType::Mod,
+            install_dir,
+            installed_version,
+            sender,
+        )
+        .await
+    }
+
+    /// Downloads a tool, such as a Proton build, alongside any other
+    /// versions of it that are already installed
+    pub async fn new_tool(
+        name: String,
+        version: String,
+        sender: Sender<DownloadManagerSignal>,
+    ) -> Result<Self, ApplicationDownloadError> {
+        let install_dir = tool_install_dir(&name, &version)
+            .ok_or_else(|| ApplicationDownloadError::InvalidTool(name.clone(), version.clone()))?;
+        let dropdata = DropData::generate(name.clone(), version.clone(), install_dir);
+
+        let mut result = Self::create(name, version, dropdata, None, sender);
+        result.download_type = DownloadType::Tool;
+
+        result.ensure_manifest_exists().await?;
+        result.check_disk_space()?;
+
+        Ok(result)
+    }
+
+    async fn new_child(
+        parent_id: String,
+        id: String,
+        version: String,
+        download_type: DownloadType,
+        install_dir: PathBuf,
+        installed_version: Option<String>,
+        sender: Sender<DownloadManagerSignal>,
+    ) -> Result<Self, ApplicationDownloadError> {
+        let (dropdata, delta) = match installed_version {
+            Some(installed_version) if installed_version != version => {
+                let previous_manifest =
+                    get_installed_manifest(&id, &installed_version, &install_dir).await?;
+                let dropdata = match DropData::read(&install_dir) {
+                    Ok(existing) if existing.game_version == version => existing,
+                    _ => {
+                        let _ = remove_dir_all(install_dir.join(DELTA_STAGING_DIR));
+                        DropData::new(id.clone(), version.clone(), install_dir.clone())
+                    }
+                };
+                let delta = DeltaSource {
+                    version: installed_version,
+                    manifest: previous_manifest,
+                    install_dir,
+                };
+                (dropdata, Some(delta))
+            }
+            _ => (
+                DropData::generate(id.clone(), version.clone(), install_dir),
+                None,
+            ),
+        };
+
+        let mut result = Self::create(id, version, dropdata, delta, sender);
+        result.download_type = download_type;
+        result.parent = Some(parent_id);
+
+        result.ensure_manifest_exists().await?;
+        result.check_disk_space()?;
+
+        Ok(result)
+    }
+
+    fn create(
+        id: String,
+        version: String,
+        dropdata: DropData,
+        delta: Option<DeltaSource>,
+        sender: Sender<DownloadManagerSignal>,
+    ) -> Self {
+        // Don't run by default
+        let control_flag = DownloadThreadControl::new(DownloadThreadControlFlag::Stop);
+
+        Self {
             id,
             version,
             control_flag,
@@ -92,23 +342,58 @@ impl GameDownloadAgent {
             context_map: Mutex::new(HashMap::new()),
             progress: Arc::new(ProgressObject::new(0, 0, sender.clone())),
             sender,
-            dropdata: stored_manifest,
+            dropdata,
             status: Mutex::new(DownloadStatus::Queued),
-        };
-
-        result.ensure_manifest_exists().await?;
+            delta,
+            delta_applied: Mutex::new(false),
+            repair: false,
+            repair_checked: Mutex::new(false),
+            download_type: DownloadType::Game,
+            parent: None,
+            verified_contexts: Mutex::new(HashSet::new()),
+            thread_budget: AtomicUsize::new(0),
+            last_speed: AtomicUsize::new(0),
+        }
+    }
 
-        let required_space = result
-            .manifest
-            .lock()
-            .unwrap()
-            .as_ref()
-            .unwrap()
-            .values()
-            .map(|e| e.lengths.iter().sum::<usize>())
-            .sum::<usize>() as u64;
+    /// Only counts chunks that `DropData` doesn't have as complete, and
+    /// only the part of those that isn't already allocated on disk.
+    /// Updates stage changed files alongside the installed ones until
+    /// they're applied, so those count in full.
+    fn check_disk_space(&self) -> Result<(), ApplicationDownloadError> {
+        let required_space = {
+            let manifest_lock = self.manifest.lock().unwrap();
+            let manifest = manifest_lock.as_ref().unwrap();
+            let unchanged_files = self
+                .delta
+                .as_ref()
+                .map(|delta| delta.unchanged_files(manifest))
+                .unwrap_or_default();
+            let base_path = match &self.delta {
+                Some(delta) => delta.staging_dir(),
+                None => self.dropdata.base_path.clone(),
+            };
+            let completed = self.dropdata.get_contexts();
+
+            manifest
+                .iter()
+                .filter(|(raw_path, _)| !unchanged_files.contains(*raw_path))
+                .map(|(raw_path, chunk)| {
+                    let incomplete = chunk
+                        .checksums
+                        .iter()
+                        .zip(chunk.lengths.iter())
+                        .filter(|(checksum, _)| !*completed.get(*checksum).unwrap_or(&false))
+                        .map(|(_, length)| *length as u64)
+                        .sum::<u64>();
+                    // Preallocated or partially written files already hold some of it
+                    let total = chunk.lengths.iter().sum::<usize>() as u64;
+                    space_needed(&base_path.join(raw_path), total, incomplete)
+                })
+                .sum::<u64>()
+        };
 
-        let available_space = get_disk_available(data_base_dir_path)? as u64;
+        let available_space = get_disk_available(self.dropdata.base_path.clone())?;
 
         if required_space > available_space {
             return Err(ApplicationDownloadError::DiskFull(
@@ -117,14 +402,73 @@ impl GameDownloadAgent {
             ));
         }
 
-        Ok(result)
+        Ok(())
+    }
+
+    /// Checked before each bucket, so we pause rather than
+    /// running out of space halfway through a write
+    fn out_of_space_for(&self, drops: &[DownloadDrop]) -> Option<(u64, u64)> {
+        // Bucket drops can share a file, so it's the file's end and what's
+        // left to write of it
+        let mut files = HashMap::<&Path, (u64, u64)>::new();
+        for drop in drops {
+            let (end, incomplete) = files.entry(drop.path.as_path()).or_default();
+            *end = (*end).max((drop.start + drop.length) as u64);
+            *incomplete += drop.length.saturating_sub(drop.resume_offset) as u64;
+        }
+        let required_space = files
+            .into_iter()
+            .map(|(path, (end, incomplete))| space_needed(path, end, incomplete))
+            .sum::<u64>();
+        if required_space == 0 {
+            return None;
+        }
+
+        match get_disk_available(self.dropdata.base_path.clone()) {
+            Ok(available_space) if required_space > available_space => {
+                Some((required_space, available_space))
+            }
+            Ok(_) => None,
+            Err(e) => {
+                warn!("couldn't check free disk space: {e}");
+                None
+            }
+        }
     }
 
     // Blocking
     pub fn setup_download(&self, app_handle: &AppHandle) -> Result<(), ApplicationDownloadError> {
+        // The parent might have been uninstalled while this was queued
+        if let Some(parent_id) = &self.parent
+            && parent_install_dir(&borrow_db_checked(), parent_id).is_none()
+        {
+            return Err(ApplicationDownloadError::NotInstalled(parent_id.clone()));
+        }
+
+        // Updates are worked out against the game's own files
+        if self.updates_game()
+            && !*self.delta_applied.lock().unwrap()
+            && let Err(e) = undeploy_mods(&self.id)
+        {
+            warn!(
+                "failed to take mods out of {} before updating: {e}",
+                self.id
+            );
+        }
+
         let mut db_lock = borrow_db_mut_checked();
-        let status = ApplicationTransientStatus::Downloading {
-            version_name: self.version.clone(),
+        let status = if self.delta.is_some() {
+            ApplicationTransientStatus::Updating {
+                version_name: self.version.clone(),
+            }
+        } else if self.repair {
+            ApplicationTransientStatus::Validating {
+                version_name: self.version.clone(),
+            }
+        } else {
+            ApplicationTransientStatus::Downloading {
+                version_name: self.version.clone(),
+            }
         };
         db_lock
             .applications
@@ -151,14 +495,38 @@ impl GameDownloadAgent {
 
         info!("beginning download for {}...", self.metadata().id);
 
-        let res = self.run().map_err(ApplicationDownloadError::Communication);
+        if let Some(delta) = &self.delta
+            && !*self.delta_applied.lock().unwrap()
+            && !self.reuse_installed_chunks(delta)?
+        {
+            return Ok(false);
+        }
+
+        if self.repair {
+            if !self.check_installed_files(app_handle)? {
+                return Ok(false);
+            }
+            // Nothing to fetch, so don't need the server at all
+            if self.verified_while_streaming() {
+                return Ok(true);
+            }
+        }
+
+        let res = self
+            .run()
+            .map_err(ApplicationDownloadError::Communication)?;
 
         debug!(
             "{} took {}ms to download",
             self.id,
             timer.elapsed().as_millis()
         );
-        res
+
+        if res && let Some(delta) = &self.delta {
+            self.apply_delta(delta)?;
+        }
+
+        Ok(res)
     }
 
     pub fn check_manifest_exists(&self) -> bool {
@@ -174,30 +542,15 @@ impl GameDownloadAgent {
     }
 
     async fn download_manifest(&self) -> Result<(), ApplicationDownloadError> {
-        let client = DROP_CLIENT_ASYNC.clone();
-        let url = generate_url(
-            &["/api/v1/client/game/manifest"],
-            &[("id", &self.id), ("version", &self.version)],
-        )
-        .map_err(ApplicationDownloadError::Communication)?;
-
-        let response = client
-            .get(url)
-            .header("Authorization", generate_authorization_header())
-            .send()
-            .await
-            .map_err(|e| ApplicationDownloadError::Communication(e.into()))?;
-
-        if response.status() != 200 {
-            return Err(ApplicationDownloadError::Communication(
-                RemoteAccessError::ManifestDownloadFailed(
-                    response.status(),
-                    response.text().await.unwrap(),
-                ),
-            ));
-        }
-
-        let manifest_download: DropManifest = response.json().await.unwrap();
+        // Resuming only needs the server for the chunks themselves
+        let manifest_download = match self.dropdata.get_manifest() {
+            Some(manifest) if self.dropdata.game_version == self.version => manifest,
+            _ => {
+                let manifest = fetch_manifest(&self.id, &self.version).await?;
+                self.dropdata.set_manifest(&manifest);
+                manifest
+            }
+        };
 
         if let Ok(mut manifest) = self.manifest.lock() {
             *manifest = Some(manifest_download);
@@ -237,8 +590,23 @@ impl GameDownloadAgent {
         let manifest = self.manifest.lock().unwrap().clone().unwrap();
         let game_id = self.id.clone();
 
-        let base_path = Path::new(&self.dropdata.base_path);
-        create_dir_all(base_path).unwrap();
+        let unchanged_files = self
+            .delta
+            .as_ref()
+            .map(|delta| delta.unchanged_files(&manifest))
+            .unwrap_or_default();
+
+        let base_path = match &self.delta {
+            Some(delta) => delta.staging_dir(),
+            None => self.dropdata.base_path.clone(),
+        };
+        create_dir_all(&base_path).unwrap();
+
+        let existing_contexts = self.dropdata.get_contexts();
+
+        // These only decide how validation and progress are split up,
+        // downloads are re-bucketed as throughput is measured
+        let target_bucket_size = BucketSizer::new(&borrow_db_checked().settings).bucket_size(0, 1);
 
         let mut buckets = Vec::new();
 
@@ -250,19 +618,37 @@ impl GameDownloadAgent {
         let mut current_bucket_size = 0;
 
         for (raw_path, chunk) in manifest {
-            let path = base_path.join(Path::new(&raw_path));
+            if unchanged_files.contains(&raw_path) {
+                continue;
+            }
+
+            let mut path = base_path.join(Path::new(&raw_path));
+
+            // Moved by an apply that was interrupted, so don't stage an empty copy
+            // that would later be moved over it
+            let already_applied = self
+                .delta
+                .as_ref()
+                .is_some_and(|delta| delta.already_applied(&raw_path, &chunk, &existing_contexts));
+            if let Some(delta) = &self.delta
+                && already_applied
+            {
+                path = delta.install_dir.join(&raw_path);
+            }
 
             let container = path.parent().unwrap();
             create_dir_all(container).unwrap();
 
             let already_exists = path.exists();
-            let file = OpenOptions::new()
-                .read(true)
-                .write(true)
-                .create(true)
-                .truncate(false)
-                .open(path.clone())
-                .unwrap();
+            let file = (!already_applied).then(|| {
+                OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .create(true)
+                    .truncate(false)
+                    .open(path.clone())
+                    .unwrap()
+            });
             let mut file_running_offset = 0;
 
             for (index, length) in chunk.lengths.iter().enumerate() {
@@ -274,10 +660,12 @@ impl GameDownloadAgent {
                     permissions: chunk.permissions,
                     path: path.clone(),
                     index,
+                    resume_offset: 0,
+                    hash_algorithm: chunk.hash_algorithm,
                 };
                 file_running_offset += *length;
 
-                if *length >= TARGET_BUCKET_SIZE {
+                if *length >= target_bucket_size {
                     // They get their own bucket
 
                     buckets.push(DownloadBucket {
@@ -289,7 +677,7 @@ impl GameDownloadAgent {
                     continue;
                 }
 
-                if current_bucket_size + *length >= TARGET_BUCKET_SIZE
+                if current_bucket_size + *length >= target_bucket_size
                     && !current_bucket.drops.is_empty()
                 {
                     // Move current bucket into list and make a new one
@@ -307,7 +695,10 @@ impl GameDownloadAgent {
             }
 
             #[cfg(target_os = "linux")]
-            if file_running_offset > 0 && !already_exists {
+            if let Some(file) = file
+                && file_running_offset > 0
+                && !already_exists
+            {
                 let _ = fallocate(file, FallocateFlags::empty(), 0, file_running_offset as u64);
             }
         }
@@ -318,7 +709,6 @@ impl GameDownloadAgent {
 
         info!("buckets: {}", buckets.len());
 
-        let existing_contexts = self.dropdata.get_contexts();
         self.dropdata.set_contexts(
             &buckets
                 .iter()
@@ -335,21 +725,98 @@ impl GameDownloadAgent {
         Ok(())
     }
 
-    fn run(&self) -> Result<bool, RemoteAccessError> {
-        self.setup_progress();
-        let max_download_threads = borrow_db_checked().settings.max_download_threads;
+    /// Copies every chunk that already exists in the installed version
+    /// into the staged files, so only new chunks hit the network
+    fn reuse_installed_chunks(
+        &self,
+        delta: &DeltaSource,
+    ) -> Result<bool, ApplicationDownloadError> {
+        let chunk_index = delta.chunk_index();
+        let buckets = self.buckets.lock().unwrap();
+        let mut context_map = self.context_map.lock().unwrap();
+        // Duplicate chunks share a context, so check against what was done before we started
+        let previously_completed = context_map.clone();
+
+        let mut reused_bytes = 0;
+        for drop in buckets.iter().flat_map(|bucket| bucket.drops.iter()) {
+            if self.control_flag.get() == DownloadThreadControlFlag::Stop {
+                return Ok(false);
+            }
+            if *previously_completed.get(&drop.checksum).unwrap_or(&false) {
+                continue;
+            }
+            let Some(location) = chunk_index.get(&drop.checksum) else {
+                continue;
+            };
+
+            copy_chunk(location, drop)
+                .map_err(|e| ApplicationDownloadError::IoError(Arc::new(e)))?;
+            context_map.insert(drop.checksum.clone(), true);
+            reused_bytes += drop.length;
+        }
 
-        debug!(
-            "downloading game: {} with {} threads",
-            self.id, max_download_threads
+        info!(
+            "reused {} from {} ({}) for update to {}",
+            format_size(reused_bytes, BINARY),
+            self.id,
+            delta.version,
+            self.version
+        );
+
+        Ok(true)
+    }
+
+    fn apply_delta(&self, delta: &DeltaSource) -> Result<(), ApplicationDownloadError> {
+        let mut delta_applied = self.delta_applied.lock().unwrap();
+        if *delta_applied {
+            return Ok(());
+        }
+
+        let manifest = self.manifest.lock().unwrap().clone().unwrap();
+        delta
+            .apply(&manifest)
+            .map_err(|e| ApplicationDownloadError::IoError(Arc::new(e)))?;
+
+        // Staged files have moved into the install dir, so validate them there
+        for bucket in self.buckets.lock().unwrap().iter_mut() {
+            for drop in bucket.drops.iter_mut() {
+                drop.path = delta.install_dir.join(&drop.filename);
+            }
+        }
+
+        self.dropdata.set_contexts(
+            &manifest
+                .values()
+                .flat_map(|chunk| chunk.checksums.iter().map(|x| (x.clone(), true)))
+                .collect::<Vec<(String, bool)>>(),
+        );
+        self.dropdata.write();
+
+        *delta_applied = true;
+
+        info!(
+            "applied update for {} from {} to {}",
+            self.id, delta.version, self.version
         );
-        let pool = ThreadPoolBuilder::new()
-            .num_threads(max_download_threads)
-            .build()
-            .unwrap();
+
+        Ok(())
+    }
+
+    fn run(&self) -> Result<bool, RemoteAccessError> {
+        self.setup_progress();
+        let max_download_threads = self.thread_budget();
+        let (sizer, retry_policy) = {
+            let db_lock = borrow_db_checked();
+            (
+                BucketSizer::new(&db_lock.settings),
+                db_lock.settings.retry_policy.clone(),
+            )
+        };
 
         let completed_contexts = Arc::new(boxcar::Vec::new());
-        let completed_indexes_loop_arc = completed_contexts.clone();
+        // Checkpoints only save progress, so everything else has to be on disk first
+        self.dropdata.write();
+        let checkpoint = DropDataCheckpoint::new();
 
         let download_context = DROP_CLIENT_SYNC
             .post(generate_url(&["/api/v2/client/context"], &[]).unwrap())
@@ -369,82 +836,154 @@ impl GameDownloadAgent {
         info!("download context: {}", download_context.context);
 
         let buckets = self.buckets.lock().unwrap();
-        pool.scope(|scope| {
+
+        let queue = {
             let context_map = self.context_map.lock().unwrap();
-            for (index, bucket) in buckets.iter().enumerate() {
-                let mut bucket = (*bucket).clone();
-                let completed_contexts = completed_indexes_loop_arc.clone();
-
-                let progress = self.progress.get(index);
-                let progress_handle = ProgressHandle::new(progress, self.progress.clone());
-
-                // If we've done this one already, skip it
-                // Note to future DecDuck, DropData gets loaded into context_map
-                let todo_drops = bucket
-                    .drops
-                    .into_iter()
-                    .filter(|e| {
-                        let todo = !*context_map.get(&e.checksum).unwrap_or(&false);
-                        if !todo {
-                            progress_handle.skip(e.length);
-                        }
-                        todo
-                    })
-                    .collect::<Vec<DownloadDrop>>();
+            let drop_count = buckets
+                .iter()
+                .map(|bucket| bucket.drops.len())
+                .sum::<usize>();
+            let mut skipped = 0;
+            // If we've done this one already, skip it
+            // Note to future DecDuck, DropData gets loaded into context_map
+            let todo_drops = buckets
+                .iter()
+                .flat_map(|bucket| bucket.drops.iter())
+                .filter(|e| {
+                    let todo = !*context_map.get(&e.checksum).unwrap_or(&false);
+                    if !todo {
+                        // Each gets its own handle from the end, as workers use
+                        // (and reset) the ones at the start
+                        self.progress_handle(drop_count - 1 - skipped)
+                            .skip(e.length);
+                        skipped += 1;
+                    }
+                    todo
+                })
+                .cloned()
+                .collect::<Vec<DownloadDrop>>();
 
-                if todo_drops.is_empty() {
-                    continue;
-                };
+            BucketQueue::new(self.id.clone(), self.version.clone(), todo_drops)
+        };
 
-                bucket.drops = todo_drops;
+        let threads = sizer.thread_count(&queue, self.download_speed(), max_download_threads);
+        debug!(
+            "downloading game: {} with {} threads ({} drops left)",
+            self.id,
+            threads,
+            queue.len()
+        );
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(threads.max(1))
+            .build()
+            .unwrap();
 
-                let sender = self.sender.clone();
+        let checksum_failures = Mutex::new(HashMap::new());
+        let failed_checksums = Arc::new(boxcar::Vec::new());
+        let out_of_space = AtomicBool::new(false);
+        pool.scope(|scope| {
+            for worker in 0..threads {
+                let queue = &queue;
+                let out_of_space = &out_of_space;
+                let checksum_failures = &checksum_failures;
+                let completed_contexts = completed_contexts.clone();
+                let checkpoint = &checkpoint;
+                let failed_checksums = failed_checksums.clone();
+                let progress_handle = self.progress_handle(worker);
+                let retry_policy = &retry_policy;
 
                 scope.spawn(move |_| {
-                    // 3 attempts
-                    for i in 0..RETRY_COUNT {
-                        let loop_progress_handle = progress_handle.clone();
-                        match download_game_bucket(
-                            &bucket,
+                    // Drops that fail their checksum get requeued straight away,
+                    // rather than waiting for validation to find them
+                    let on_drop_complete = |drop: &DownloadDrop, verified: bool| {
+                        if verified {
+                            completed_contexts.push(drop.checksum.clone());
+                            // So a crash doesn't throw away everything downloaded this run
+                            self.dropdata.set_context(drop.checksum.clone(), true);
+                            checkpoint.record(&self.dropdata, drop.length);
+                            return;
+                        }
+
+                        self.dropdata.set_context(drop.checksum.clone(), false);
+
+                        let mut failures = checksum_failures.lock().unwrap();
+                        let attempts = failures
+                            .entry((drop.filename.clone(), drop.index))
+                            .or_insert(0);
+                        *attempts += 1;
+                        if *attempts >= retry_policy.max_attempts {
+                            failed_checksums.push(drop.checksum.clone());
+                            return;
+                        }
+
+                        info!(
+                            "re-fetching {} (chunk {}) after it failed its checksum",
+                            drop.filename, drop.index
+                        );
+                        let mut drop = drop.clone();
+                        drop.resume_offset = 0;
+                        queue.push(drop);
+                    };
+
+                    // Threads past the budget bow out if it shrinks mid-download
+                    while self.control_flag.get() == DownloadThreadControlFlag::Go
+                        && worker < self.thread_budget()
+                    {
+                        let bucket_size = sizer.bucket_size(self.download_speed(), threads);
+                        let Some(bucket) = queue.next_bucket(bucket_size) else {
+                            return;
+                        };
+
+                        if let Some((required, available)) = self.out_of_space_for(&bucket.drops) {
+                            // The drops are left incomplete, so resuming picks them up again
+                            if !out_of_space.swap(true, Ordering::AcqRel) {
+                                warn!("not enough space left to continue downloading {}", self.id);
+                                self.sender
+                                    .send(DownloadManagerSignal::Error(
+                                        self.metadata(),
+                                        ApplicationDownloadError::DiskFull(required, available),
+                                    ))
+                                    .unwrap();
+                            }
+                            return;
+                        }
+
+                        if !self.download_bucket(
+                            bucket,
                             download_context,
-                            &self.control_flag,
-                            loop_progress_handle,
+                            progress_handle.clone(),
+                            retry_policy,
+                            &on_drop_complete,
                         ) {
-                            Ok(true) => {
-                                for drop in bucket.drops {
-                                    completed_contexts.push(drop.checksum);
-                                }
-                                return;
-                            }
-                            Ok(false) => return,
-                            Err(e) => {
-                                warn!("game download agent error: {e}");
-
-                                let retry = matches!(
-                                    &e,
-                                    ApplicationDownloadError::Communication(_)
-                                        | ApplicationDownloadError::Checksum
-                                        | ApplicationDownloadError::Lock
-                                );
-
-                                if i == RETRY_COUNT - 1 || !retry {
-                                    warn!("retry logic failed, not re-attempting.");
-                                    sender.send(DownloadManagerSignal::Error(e)).unwrap();
-                                    return;
-                                }
-                            }
+                            return;
                         }
                     }
                 });
             }
         });
 
+        if !failed_checksums.is_empty() {
+            warn!(
+                "{} chunks of {} still failed their checksum, giving up",
+                failed_checksums.count(),
+                self.id
+            );
+            self.sender
+                .send(DownloadManagerSignal::Error(
+                    self.metadata(),
+                    ApplicationDownloadError::Checksum,
+                ))
+                .unwrap();
+        }
+
         let newly_completed = completed_contexts.clone();
 
         let completed_lock_len = {
             let mut context_map_lock = self.context_map.lock().unwrap();
+            let mut verified_contexts = self.verified_contexts.lock().unwrap();
             for (_, item) in newly_completed.iter() {
                 context_map_lock.insert(item.clone(), true);
+                verified_contexts.insert(item.clone());
             }
 
             context_map_lock.values().filter(|x| **x).count()
@@ -479,6 +1018,60 @@ impl GameDownloadAgent {
         Ok(true)
     }
 
+    fn progress_handle(&self, index: usize) -> ProgressHandle {
+        ProgressHandle::new(self.progress.get(index), self.progress.clone())
+    }
+
+    /// Returns false if the bucket couldn't be downloaded, and the
+    /// download manager has been told why
+    fn download_bucket(
+        &self,
+        mut bucket: DownloadBucket,
+        download_context: &DownloadContext,
+        progress_handle: ProgressHandle,
+        retry_policy: &RetryPolicy,
+        on_drop_complete: &dyn Fn(&DownloadDrop, bool),
+    ) -> bool {
+        // Each attempt only requests what the last one didn't finish
+        let mut attempt = 0;
+        loop {
+            let loop_progress_handle = progress_handle.clone();
+            let e = match download_game_bucket(
+                &mut bucket,
+                download_context,
+                &self.control_flag,
+                loop_progress_handle,
+                on_drop_complete,
+            ) {
+                Ok(_) => return true,
+                Err(e) => e,
+            };
+
+            warn!("game download agent error: {e}");
+
+            attempt += 1;
+            if attempt >= retry_policy.max_attempts || !e.is_transient() {
+                warn!("retry logic failed, not re-attempting.");
+                self.sender
+                    .send(DownloadManagerSignal::Error(self.metadata(), e))
+                    .unwrap();
+                return false;
+            }
+
+            let backoff = retry_policy.backoff(attempt - 1);
+            info!(
+                "retrying bucket for {} in {}ms (attempt {}/{})",
+                self.id,
+                backoff.as_millis(),
+                attempt + 1,
+                retry_policy.max_attempts
+            );
+            if !wait_for_retry(backoff, &self.control_flag) {
+                return false;
+            }
+        }
+    }
+
     fn setup_validate(&self, app_handle: &AppHandle) {
         self.setup_progress();
 
@@ -497,6 +1090,20 @@ impl GameDownloadAgent {
     }
 
     pub fn validate(&self, app_handle: &AppHandle) -> Result<bool, ApplicationDownloadError> {
+        if self.verified_while_streaming() {
+            info!(
+                "every chunk of {} was verified while downloading, skipping validation",
+                self.id
+            );
+            return Ok(true);
+        }
+
+        self.validate_chunks(app_handle)
+    }
+
+    /// Validates every chunk, marking the ones that match as done,
+    /// so only the ones that don't get downloaded next time round
+    fn validate_chunks(&self, app_handle: &AppHandle) -> Result<bool, ApplicationDownloadError> {
         self.setup_validate(app_handle);
 
         let buckets = self.buckets.lock().unwrap();
@@ -505,7 +1112,9 @@ impl GameDownloadAgent {
             .into_iter()
             .flat_map(|e| -> Vec<DropValidateContext> { e.into() })
             .collect();
-        let max_download_threads = borrow_db_checked().settings.max_download_threads;
+        drop(buckets);
+        let max_download_threads = self.thread_budget();
+        let mod_owned = self.mod_owned_paths();
 
         info!("{} validation contexts", contexts.len());
         let pool = ThreadPoolBuilder::new()
@@ -514,28 +1123,52 @@ impl GameDownloadAgent {
             .unwrap();
 
         let invalid_chunks = Arc::new(boxcar::Vec::new());
+        let valid_chunks = Arc::new(boxcar::Vec::new());
         pool.scope(|scope| {
             for (index, context) in contexts.iter().enumerate() {
                 let current_progress = self.progress.get(index);
                 let progress_handle = ProgressHandle::new(current_progress, self.progress.clone());
+                // A mod replaced this file, so it was never going to match
+                if mod_owned.contains(&context.path) {
+                    progress_handle.skip(context.length);
+                    valid_chunks.push(context.checksum.clone());
+                    continue;
+                }
                 let invalid_chunks_scoped = invalid_chunks.clone();
+                let valid_chunks_scoped = valid_chunks.clone();
                 let sender = self.sender.clone();
 
                 scope.spawn(move |_| {
                     match validate_game_chunk(context, &self.control_flag, progress_handle) {
-                        Ok(true) => {}
+                        Ok(true) => {
+                            valid_chunks_scoped.push(context.checksum.clone());
+                        }
                         Ok(false) => {
                             invalid_chunks_scoped.push(context.checksum.clone());
                         }
                         Err(e) => {
                             error!("{e}");
-                            sender.send(DownloadManagerSignal::Error(e)).unwrap();
+                            sender
+                                .send(DownloadManagerSignal::Error(self.metadata(), e))
+                                .unwrap();
                         }
                     }
                 });
             }
         });
 
+        if self.control_flag.get() == DownloadThreadControlFlag::Stop {
+            return Ok(false);
+        }
+
+        {
+            let mut verified_contexts = self.verified_contexts.lock().unwrap();
+            for (_, context) in valid_chunks.iter() {
+                self.dropdata.set_context(context.clone(), true);
+                verified_contexts.insert(context.clone());
+            }
+        }
+
         // If there are any contexts left which are false
         if !invalid_chunks.is_empty() {
             info!("validation of game id {} failed", self.id);
@@ -552,16 +1185,140 @@ impl GameDownloadAgent {
         Ok(true)
     }
 
+    /// Returns false if stopped before every installed file was checked
+    fn check_installed_files(
+        &self,
+        app_handle: &AppHandle,
+    ) -> Result<bool, ApplicationDownloadError> {
+        let mut repair_checked = self.repair_checked.lock().unwrap();
+        if *repair_checked {
+            return Ok(true);
+        }
+
+        info!("checking installed files of {} for repair", self.id);
+        let intact = self.validate_chunks(app_handle)?;
+        if self.control_flag.get() == DownloadThreadControlFlag::Stop {
+            return Ok(false);
+        }
+        if intact {
+            info!("every chunk of {} is intact", self.id);
+            self.dropdata.write();
+        }
+
+        *self.context_map.lock().unwrap() = self.dropdata.get_contexts();
+        *repair_checked = true;
+
+        Ok(true)
+    }
+
+    /// What this run has measured so far, or the last run if it hasn't yet
+    fn download_speed(&self) -> usize {
+        match self.progress.get_speed() {
+            0 => self.last_speed.load(Ordering::Acquire),
+            speed => {
+                self.last_speed.store(speed, Ordering::Release);
+                speed
+            }
+        }
+    }
+
+    fn thread_budget(&self) -> usize {
+        match self.thread_budget.load(Ordering::Acquire) {
+            0 => borrow_db_checked().settings.max_download_threads,
+            threads => threads,
+        }
+    }
+
+    /// Chunks that were skipped (already on disk, or reused from the
+    /// installed version) weren't hashed by us, so they still need validating
+    fn verified_while_streaming(&self) -> bool {
+        let verified_contexts = self.verified_contexts.lock().unwrap();
+        self.buckets
+            .lock()
+            .unwrap()
+            .iter()
+            .flat_map(|bucket| bucket.drops.iter())
+            .all(|drop| verified_contexts.contains(&drop.checksum))
+    }
+
     pub fn cancel(&self, app_handle: &AppHandle) {
-        // See docs on usage
-        set_partially_installed(
-            &self.metadata(),
-            self.dropdata.base_path.to_str().unwrap().to_string(),
-            Some(app_handle),
-        );
+        // DLC, mods and tools aren't tracked until they're complete,
+        // and the .dropdata lets them resume
+        if self.repair
+            || self.download_type != DownloadType::Game
+            || (self.delta.is_some() && !*self.delta_applied.lock().unwrap())
+        {
+            // The installed version hasn't been touched, so it stays installed
+            let mut db_lock = borrow_db_mut_checked();
+            db_lock
+                .applications
+                .transient_statuses
+                .remove(&self.metadata());
+            push_game_update(
+                app_handle,
+                &self.id,
+                self.installed_version(&db_lock),
+                GameStatusManager::fetch_state(&self.id, &db_lock),
+            );
+        } else {
+            // See docs on usage
+            set_partially_installed(
+                &self.metadata(),
+                self.dropdata.base_path.to_str().unwrap().to_string(),
+                Some(app_handle),
+            );
+        }
 
         self.dropdata.write();
     }
+
+    /// Files our mods put in the game's install directory
+    fn mod_owned_paths(&self) -> HashSet<PathBuf> {
+        if self.download_type != DownloadType::Game {
+            return HashSet::new();
+        }
+        mod_owned_files(&borrow_db_checked(), &self.id)
+            .into_iter()
+            .map(|relative_path| self.dropdata.base_path.join(relative_path))
+            .collect()
+    }
+
+    fn updates_game(&self) -> bool {
+        self.download_type == DownloadType::Game && self.delta.is_some()
+    }
+
+    /// Puts back the mods we took out for an update
+    fn restore_mods(&self) {
+        if self.updates_game()
+            && let Err(e) = redeploy_mods(&self.id)
+        {
+            warn!("failed to restore mods for {}: {e}", self.id);
+        }
+    }
+
+    /// Looks for files the version we just installed doesn't ship,
+    /// such as ones left behind by an older version
+    fn reconcile_stale_files(&self, app_handle: &AppHandle, remove: bool) {
+        let Some(manifest) = self.manifest.lock().unwrap().clone() else {
+            return;
+        };
+        reconcile_stale_files(
+            app_handle,
+            &self.id,
+            &self.dropdata.base_path,
+            &manifest,
+            remove,
+        );
+    }
+
+    fn installed_version(&self, db: &Database) -> Option<GameVersion> {
+        let meta = db.applications.installed_game_version.get(&self.id)?;
+        db.applications
+            .game_versions
+            .get(&self.id)?
+            .get(meta.version.as_ref()?)
+            .cloned()
+    }
 }
 
 impl Downloadable for GameDownloadAgent {
@@ -583,11 +1340,41 @@ impl Downloadable for GameDownloadAgent {
         self.control_flag.clone()
     }
 
+    fn set_thread_budget(&self, threads: usize) {
+        self.thread_budget.store(threads, Ordering::Release);
+    }
+
     fn metadata(&self) -> DownloadableMetadata {
         DownloadableMetadata {
             id: self.id.clone(),
             version: Some(self.version.clone()),
-            download_type: DownloadType::Game,
+            download_type: self.download_type,
+        }
+    }
+
+    fn install_dir(&self) -> PathBuf {
+        self.dropdata.base_path.clone()
+    }
+
+    fn queue_entry(&self) -> QueuedDownload {
+        let kind = match (&self.parent, self.download_type) {
+            (Some(parent_id), DownloadType::Mod) => QueuedDownloadKind::Mod {
+                parent_id: parent_id.clone(),
+            },
+            (Some(parent_id), _) => QueuedDownloadKind::Dlc {
+                parent_id: parent_id.clone(),
+            },
+            (None, DownloadType::Tool) => QueuedDownloadKind::Tool,
+            _ if self.repair => QueuedDownloadKind::Repair,
+            _ if self.delta.is_some() => QueuedDownloadKind::Update,
+            _ => QueuedDownloadKind::Install {
+                base_dir: self.dropdata.base_path.parent().unwrap().to_path_buf(),
+            },
+        };
+        QueuedDownload {
+            meta: self.metadata(),
+            kind,
+            paused: false,
         }
     }
 
@@ -612,22 +1399,74 @@ impl Downloadable for GameDownloadAgent {
         push_game_update(
             app_handle,
             &self.id,
-            None,
+            self.installed_version(&handle),
             GameStatusManager::fetch_state(&self.id, &handle),
         );
+        drop(handle);
+
+        self.restore_mods();
     }
 
     fn on_complete(&self, app_handle: &tauri::AppHandle) {
+        if self.download_type == DownloadType::Tool {
+            borrow_db_mut_checked()
+                .applications
+                .transient_statuses
+                .remove(&self.metadata());
+            set_tool_installed(&self.id, &self.version, app_handle);
+            return;
+        }
+
+        if let Some(parent_id) = &self.parent {
+            borrow_db_mut_checked()
+                .applications
+                .transient_statuses
+                .remove(&self.metadata());
+            if self.download_type == DownloadType::Mod {
+                set_mod_installed(parent_id, &self.id, self.version.clone(), app_handle);
+                info!("installed mod {} for {parent_id}", self.id);
+            } else {
+                set_dlc_installed(parent_id, &self.id, self.version.clone(), app_handle);
+                info!("installed DLC {} for {parent_id}", self.id);
+            }
+            return;
+        }
+
+        if self.repair {
+            // Nothing about the installed version changed
+            let mut db_lock = borrow_db_mut_checked();
+            db_lock
+                .applications
+                .transient_statuses
+                .remove(&self.metadata());
+            push_game_update(
+                app_handle,
+                &self.id,
+                self.installed_version(&db_lock),
+                GameStatusManager::fetch_state(&self.id, &db_lock),
+            );
+            drop(db_lock);
+            info!("finished repairing {}", self.id);
+            self.reconcile_stale_files(app_handle, false);
+            return;
+        }
+
         on_game_complete(
             &self.metadata(),
             self.dropdata.base_path.to_string_lossy().to_string(),
             app_handle,
         )
         .unwrap();
+        self.restore_mods();
+
+        let remove =
+            self.delta.is_some() && borrow_db_checked().settings.remove_stale_files_after_update;
+        self.reconcile_stale_files(app_handle, remove);
     }
 
     fn on_cancelled(&self, app_handle: &tauri::AppHandle) {
         self.cancel(app_handle);
+        self.restore_mods();
         /*
            on_game_incomplete(
                &self.metadata(),
@@ -2,17 +2,21 @@ use crate::download_manager::util::download_thread_control_flag::{
     DownloadThreadControl, DownloadThreadControlFlag,
 };
 use crate::download_manager::util::progress_object::ProgressHandle;
+use crate::download_manager::util::rate_limiter::DOWNLOAD_RATE_LIMITER;
 use crate::error::application_download_error::ApplicationDownloadError;
 use crate::error::drop_server_error::DropServerError;
 use crate::error::remote_access_error::RemoteAccessError;
+use crate::games::downloads::hasher::{ChunkHasher, HashAlgorithm};
 use crate::games::downloads::manifest::{ChunkBody, DownloadBucket, DownloadContext, DownloadDrop};
+use crate::peer::{PEER_CLIENT, chunk_url, known_peers, mark_unreachable};
 use crate::remote::auth::generate_authorization_header;
 use crate::remote::requests::generate_url;
 use crate::remote::utils::DROP_CLIENT_SYNC;
-use log::{info, warn};
-use md5::{Context, Digest};
-use reqwest::blocking::Response;
+use log::{debug, info, warn};
+use reqwest::header::{ACCEPT_ENCODING, CONTENT_ENCODING};
 
+use std::cell::Cell;
+use std::collections::HashSet;
 use std::fs::{Permissions, set_permissions};
 use std::io::Read;
 #[cfg(unix)]
@@ -27,27 +31,46 @@ use std::{
 static MAX_PACKET_LENGTH: usize = 4096 * 4;
 
 pub struct DropWriter<W: Write> {
-    hasher: Context,
+    hasher: ChunkHasher,
     destination: BufWriter<W>,
     progress: ProgressHandle,
 }
 impl DropWriter<File> {
-    fn new(path: PathBuf, progress: ProgressHandle) -> Result<Self, io::Error> {
+    fn new(
+        path: PathBuf,
+        hash_algorithm: HashAlgorithm,
+        progress: ProgressHandle,
+    ) -> Result<Self, io::Error> {
         let destination = OpenOptions::new()
+            .read(true)
             .write(true)
             .create(true)
             .truncate(false)
             .open(&path)?;
         Ok(Self {
             destination: BufWriter::with_capacity(1024 * 1024, destination),
-            hasher: Context::new(),
+            hasher: ChunkHasher::new(hash_algorithm),
             progress,
         })
     }
 
-    fn finish(mut self) -> io::Result<Digest> {
+    /// Hashes the bytes a previous attempt already wrote, leaving
+    /// the writer positioned straight after them
+    fn resume(&mut self, length: usize) -> io::Result<()> {
+        let file = self.destination.get_mut();
+        let read = io::copy(&mut file.take(length as u64), &mut self.hasher)?;
+        if read as usize != length {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("expected {length} bytes on disk to resume from, found {read}"),
+            ));
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<String> {
         self.flush()?;
-        Ok(self.hasher.compute())
+        Ok(self.hasher.finalize())
     }
 }
 // Write automatically pushes to file and hasher
@@ -74,55 +97,94 @@ impl Seek for DropWriter<File> {
     }
 }
 
-pub struct DropDownloadPipeline<'a, R: Read, W: Write> {
+/// Counts bytes against the rate limit as they come off the network,
+/// for when that isn't what ends up on disk
+struct RateLimitedReader<R: Read> {
+    inner: R,
+}
+
+impl<R: Read> Read for RateLimitedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        DOWNLOAD_RATE_LIMITER.acquire(read);
+        Ok(read)
+    }
+}
+
+pub struct DropDownloadPipeline<'a, R: Read> {
     pub source: R,
     pub drops: Vec<DownloadDrop>,
-    pub destination: Vec<DropWriter<W>>,
     pub control_flag: &'a DownloadThreadControl,
+    progress: ProgressHandle,
+    // The drop being written, and how much of it is on disk
+    current: usize,
+    current_written: usize,
+    // Peers are on the LAN, so they don't count against the download limit
+    rate_limited: bool,
 }
 
-impl<'a> DropDownloadPipeline<'a, Response, File> {
+impl<'a, R: Read> DropDownloadPipeline<'a, R> {
     fn new(
-        source: Response,
+        source: R,
         drops: Vec<DownloadDrop>,
         control_flag: &'a DownloadThreadControl,
         progress: ProgressHandle,
-    ) -> Result<Self, io::Error> {
-        Ok(Self {
+    ) -> Self {
+        Self {
             source,
-            destination: drops
-                .iter()
-                .map(|drop| DropWriter::new(drop.path.clone(), progress.clone()))
-                .try_collect()?,
             drops,
             control_flag,
-        })
+            progress,
+            current: 0,
+            current_written: 0,
+            rate_limited: true,
+        }
     }
 
-    fn copy(&mut self) -> Result<bool, io::Error> {
-        let mut copy_buffer = [0u8; MAX_PACKET_LENGTH];
-        for (index, drop) in self.drops.iter().enumerate() {
-            let destination = self
-                .destination
-                .get_mut(index)
-                .ok_or(io::Error::other("no destination"))
-                .unwrap();
-            let mut remaining = drop.length;
-            if drop.start != 0 {
-                destination.seek(SeekFrom::Start(drop.start.try_into().unwrap()))?;
+    /// `on_drop_complete` is told whether each written drop matched its checksum
+    fn copy(&mut self, on_drop_complete: &dyn Fn(&DownloadDrop, bool)) -> Result<bool, io::Error> {
+        while self.current < self.drops.len() {
+            let drop = self.drops[self.current].clone();
+
+            let mut destination = DropWriter::new(
+                drop.path.clone(),
+                drop.hash_algorithm,
+                self.progress.clone(),
+            )?;
+            destination.seek(SeekFrom::Start(drop.start.try_into().unwrap()))?;
+            if drop.resume_offset != 0 {
+                destination.resume(drop.resume_offset)?;
             }
-            loop {
-                let size = MAX_PACKET_LENGTH.min(remaining);
-                self.source.read_exact(&mut copy_buffer[0..size])?;
-                remaining -= size;
+            self.current_written = drop.resume_offset;
 
-                destination.write_all(&copy_buffer[0..size])?;
+            if let Err(e) = self.copy_drop(&mut destination, drop.length - drop.resume_offset) {
+                // Make sure what we did get is on disk, so the retry can pick up from it
+                let _ = destination.flush();
+                return Err(e);
+            }
+
+            let checksum = destination.finish()?;
+            let verified = checksum == drop.checksum;
+            if !verified {
+                warn!(
+                    "checksum mismatch for {} (chunk {}), expected {}, got {}",
+                    drop.filename, drop.index, drop.checksum, checksum
+                );
+                // It'll be downloaded again, so don't count it twice
+                self.progress.revert(drop.length);
+            }
 
-                if remaining == 0 {
-                    break;
-                };
+            // If we complete the file, set the permissions (if on Linux)
+            #[cfg(unix)]
+            {
+                let permissions = Permissions::from_mode(drop.permissions);
+                set_permissions(drop.path.clone(), permissions)?;
             }
 
+            on_drop_complete(&drop, verified);
+            self.current += 1;
+            self.current_written = 0;
+
             if self.control_flag.get() == DownloadThreadControlFlag::Stop {
                 return Ok(false);
             }
@@ -131,21 +193,146 @@ impl<'a> DropDownloadPipeline<'a, Response, File> {
         Ok(true)
     }
 
-    fn finish(self) -> Result<Vec<Digest>, io::Error> {
-        let checksums = self
-            .destination
-            .into_iter()
-            .map(|e| e.finish())
-            .try_collect()?;
-        Ok(checksums)
+    fn copy_drop(&mut self, destination: &mut DropWriter<File>, length: usize) -> io::Result<()> {
+        let mut copy_buffer = [0u8; MAX_PACKET_LENGTH];
+        let mut remaining = length;
+        while remaining > 0 {
+            let size = MAX_PACKET_LENGTH.min(remaining);
+            if self.rate_limited {
+                DOWNLOAD_RATE_LIMITER.acquire(size);
+            }
+            self.source.read_exact(&mut copy_buffer[0..size])?;
+
+            destination.write_all(&copy_buffer[0..size])?;
+            remaining -= size;
+            self.current_written += size;
+        }
+        Ok(())
+    }
+
+    /// Drops that still need downloading, with the partially written
+    /// one set to resume where it left off
+    fn remaining(&self) -> Vec<DownloadDrop> {
+        let mut remaining = self.drops[self.current..].to_vec();
+        if let Some(partial) = remaining.first_mut() {
+            partial.resume_offset = self.current_written;
+        }
+        remaining
+    }
+}
+
+enum PeerFetch {
+    Fetched,
+    // The peer doesn't have it, so probably not the rest of the bucket either
+    Missing,
+    Failed,
+}
+
+/// Fetches what it can of the bucket from LAN peers, leaving the rest
+/// for the server. Peers aren't trusted, so anything that fails its
+/// checksum is left in the bucket too.
+fn download_from_peers(
+    bucket: &mut DownloadBucket,
+    peers: &[String],
+    control_flag: &DownloadThreadControl,
+    progress: &ProgressHandle,
+    on_drop_complete: &dyn Fn(&DownloadDrop, bool),
+) {
+    // Not asked again for the rest of the bucket
+    let mut skipped = HashSet::new();
+    let mut remaining = Vec::new();
+    for drop in std::mem::take(&mut bucket.drops) {
+        let mut fetched = false;
+        if drop.resume_offset == 0 {
+            for peer in peers {
+                if skipped.contains(peer) || control_flag.get() != DownloadThreadControlFlag::Go {
+                    continue;
+                }
+                match download_drop_from_peer(peer, &drop, control_flag, progress) {
+                    PeerFetch::Fetched => {
+                        fetched = true;
+                        break;
+                    }
+                    PeerFetch::Missing => {
+                        skipped.insert(peer);
+                    }
+                    PeerFetch::Failed => {
+                        // Stopping cuts the transfer short, which isn't the peer's fault
+                        if control_flag.get() == DownloadThreadControlFlag::Go {
+                            mark_unreachable(peer);
+                        }
+                        skipped.insert(peer);
+                    }
+                }
+            }
+        }
+        if fetched {
+            on_drop_complete(&drop, true);
+        } else {
+            remaining.push(drop);
+        }
+    }
+    bucket.drops = remaining;
+}
+
+fn download_drop_from_peer(
+    peer: &str,
+    drop: &DownloadDrop,
+    control_flag: &DownloadThreadControl,
+    progress: &ProgressHandle,
+) -> PeerFetch {
+    let response = match PEER_CLIENT.get(chunk_url(peer, &drop.checksum)).send() {
+        Ok(response) => response,
+        Err(e) => {
+            debug!("couldn't reach peer {peer}: {e}");
+            return PeerFetch::Failed;
+        }
+    };
+    if response.status() == 404 {
+        return PeerFetch::Missing;
+    }
+    if response.status() != 200 || response.content_length() != Some(drop.length as u64) {
+        return PeerFetch::Failed;
+    }
+
+    let verified = Cell::new(false);
+    let mut pipeline =
+        DropDownloadPipeline::new(response, vec![drop.clone()], control_flag, progress.clone());
+    pipeline.rate_limited = false;
+
+    match pipeline.copy(&|_, matched| verified.set(matched)) {
+        Ok(_) if verified.get() => {
+            debug!(
+                "fetched {} (chunk {}) from peer {peer}",
+                drop.filename, drop.index
+            );
+            PeerFetch::Fetched
+        }
+        // The pipeline already took it off the progress
+        Ok(_) => {
+            warn!(
+                "peer {peer} sent a bad copy of {} (chunk {})",
+                drop.filename, drop.index
+            );
+            PeerFetch::Failed
+        }
+        Err(e) => {
+            progress.revert(pipeline.current_written);
+            debug!(
+                "failed to fetch {} (chunk {}) from peer {peer}: {e}",
+                drop.filename, drop.index
+            );
+            PeerFetch::Failed
+        }
     }
 }
 
 pub fn download_game_bucket(
-    bucket: &DownloadBucket,
+    bucket: &mut DownloadBucket,
     ctx: &DownloadContext,
     control_flag: &DownloadThreadControl,
     progress: ProgressHandle,
+    on_drop_complete: &dyn Fn(&DownloadDrop, bool),
 ) -> Result<bool, ApplicationDownloadError> {
     // If we're paused
     if control_flag.get() == DownloadThreadControlFlag::Stop {
@@ -153,6 +340,17 @@ pub fn download_game_bucket(
         return Ok(false);
     }
 
+    let peers = known_peers();
+    if !peers.is_empty() {
+        download_from_peers(bucket, &peers, control_flag, &progress, on_drop_complete);
+        if bucket.drops.is_empty() {
+            return Ok(control_flag.get() == DownloadThreadControlFlag::Go);
+        }
+        if control_flag.get() == DownloadThreadControlFlag::Stop {
+            return Ok(false);
+        }
+    }
+
     let header = generate_authorization_header();
 
     let url = generate_url(&["/api/v2/client/chunk"], &[])
@@ -164,22 +362,26 @@ pub fn download_game_bucket(
         .post(url)
         .json(&body)
         .header("Authorization", header)
+        // Servers that don't support it just ignore this
+        .header(ACCEPT_ENCODING, "zstd")
         .send()
         .map_err(|e| ApplicationDownloadError::Communication(e.into()))?;
 
     if response.status() != 200 {
-        info!("chunk request got status code: {}", response.status());
+        let status = response.status();
+        info!("chunk request got status code: {status}");
         let raw_res = response.text().map_err(|e| {
             ApplicationDownloadError::Communication(RemoteAccessError::FetchError(e.into()))
         })?;
         info!("{raw_res}");
-        if let Ok(err) = serde_json::from_str::<DropServerError>(&raw_res) {
-            return Err(ApplicationDownloadError::Communication(
-                RemoteAccessError::InvalidResponse(err),
-            ));
-        }
+        // Proxies in front of the server don't answer in our format,
+        // but the status still tells us if it's worth retrying
+        let err = serde_json::from_str::<DropServerError>(&raw_res).unwrap_or(DropServerError {
+            status_code: status.as_u16() as usize,
+            status_message: raw_res,
+        });
         return Err(ApplicationDownloadError::Communication(
-            RemoteAccessError::UnparseableResponse(raw_res),
+            RemoteAccessError::InvalidResponse(err),
         ));
     }
 
@@ -192,56 +394,51 @@ pub fn download_game_bucket(
         .to_str()
         .unwrap();
 
+    let mut drops = bucket.drops.clone();
     for (i, raw_length) in lengths.split(",").enumerate() {
         let length = raw_length.parse::<usize>().unwrap_or(0);
-        let Some(drop) = bucket.drops.get(i) else {
-            warn!(
-                "invalid number of Content-Lengths recieved: {i}, {lengths}"
-            );
+        let Some(drop) = drops.get_mut(i) else {
+            warn!("invalid number of Content-Lengths recieved: {i}, {lengths}");
             return Err(ApplicationDownloadError::DownloadError);
         };
-        if drop.length != length {
+        if drop.resume_offset != 0 && drop.length == length {
+            // Server ignored the offset and sent the whole thing, so start over
+            drop.resume_offset = 0;
+        }
+        if drop.length - drop.resume_offset != length {
             warn!(
                 "for {}, expected {}, got {} ({})",
-                drop.filename, drop.length, raw_length, length
+                drop.filename,
+                drop.length - drop.resume_offset,
+                raw_length,
+                length
             );
             return Err(ApplicationDownloadError::DownloadError);
         }
     }
 
-    let mut pipeline =
-        DropDownloadPipeline::new(response, bucket.drops.clone(), control_flag, progress)
-            .map_err(|e| ApplicationDownloadError::IoError(Arc::new(e)))?;
-
-    let completed = pipeline
-        .copy()
-        .map_err(|e| ApplicationDownloadError::IoError(Arc::new(e)))?;
-    if !completed {
-        return Ok(false);
-    }
+    let compressed = response
+        .headers()
+        .get(CONTENT_ENCODING)
+        .is_some_and(|encoding| encoding == "zstd");
 
-    // If we complete the file, set the permissions (if on Linux)
-    #[cfg(unix)]
-    {
-        for drop in bucket.drops.iter() {
-            let permissions = Permissions::from_mode(drop.permissions);
-            set_permissions(drop.path.clone(), permissions)
-                .map_err(|e| ApplicationDownloadError::IoError(Arc::new(e)))?;
-        }
-    }
+    // Content-Lengths are always uncompressed, so everything
+    // past the decoder works in uncompressed bytes
+    let source: Box<dyn Read> = if compressed {
+        let decoder = zstd::Decoder::new(RateLimitedReader { inner: response })
+            .map_err(|e| ApplicationDownloadError::IoError(Arc::new(e)))?;
+        Box::new(decoder)
+    } else {
+        Box::new(response)
+    };
 
-    let checksums = pipeline
-        .finish()
-        .map_err(|e| ApplicationDownloadError::IoError(Arc::new(e)))?;
+    let mut pipeline = DropDownloadPipeline::new(source, drops, control_flag, progress);
+    // Already limited on the compressed bytes
+    pipeline.rate_limited = !compressed;
 
-    for (index, drop) in bucket.drops.iter().enumerate() {
-        let res = hex::encode(**checksums.get(index).unwrap());
-        if res != drop.checksum {
-            warn!("context didn't match... doing nothing because we will validate later.");
-            // return Ok(false);
-            // return Err(ApplicationDownloadError::Checksum);
-        }
-    }
+    let copy_result = pipeline.copy(on_drop_complete);
+    // Whatever happened, only leave what's left for the next attempt
+    bucket.drops = pipeline.remaining();
 
-    Ok(true)
+    copy_result.map_err(|e| ApplicationDownloadError::IoError(Arc::new(e)))
 }
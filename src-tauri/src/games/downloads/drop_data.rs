@@ -1,13 +1,38 @@
 use std::{
-    collections::HashMap, fs::File, io::{self, Read, Write}, path::{Path, PathBuf}
+    collections::HashMap,
+    fs::{self, File, rename},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    sync::{
+        Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
 };
 
-use log::error;
-use native_model::{Decode, Encode};
+use chrono::Utc;
+use log::{debug, error, warn};
+use native_model::{Decode, Encode, rmp_serde_1_3::RmpSerde};
 
-pub type DropData = v1::DropData;
+use crate::games::downloads::{
+    hasher::{ChunkHasher, HashAlgorithm},
+    manifest::DropManifest,
+};
+
+pub type DropData = v2::DropData;
 
 pub static DROP_DATA_PATH: &str = ".dropdata";
+// Written in full, then renamed over DROP_DATA_PATH
+pub static DROP_DATA_TEMP_PATH: &str = ".dropdata.tmp";
+// Contexts checkpointed since the last full write, which win over the ones in DROP_DATA_PATH
+pub static DROP_DATA_PROGRESS_PATH: &str = ".dropdata.progress";
+pub static DROP_DATA_PROGRESS_TEMP_PATH: &str = ".dropdata.progress.tmp";
+
+// So writes can't land out of order, or share a temporary file
+static WRITE_LOCK: Mutex<()> = Mutex::new(());
+
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(15);
+const CHECKPOINT_BYTES: usize = 256 * 1000 * 1000;
 
 pub mod v1 {
     use std::{collections::HashMap, path::PathBuf, sync::Mutex};
@@ -23,14 +48,72 @@ pub mod v1 {
         pub contexts: Mutex<HashMap<String, bool>>,
         pub base_path: PathBuf,
     }
+}
+
+pub mod v2 {
+    use std::{collections::HashMap, path::PathBuf, sync::Mutex};
+
+    use native_model::native_model;
+    use serde::{Deserialize, Serialize};
+
+    use crate::games::downloads::{hasher::HashAlgorithm, manifest::DropManifest};
+
+    use super::v1;
+
+    /// Everything needed to resume, repair or verify an install without
+    /// asking the server, which `v1` couldn't do
+    #[derive(Serialize, Deserialize, Debug)]
+    #[native_model(id = 9, version = 2, with = native_model::rmp_serde_1_3::RmpSerde, from = v1::DropData)]
+    pub struct DropData {
+        pub game_id: String,
+        pub game_version: String,
+        pub contexts: Mutex<HashMap<String, bool>>,
+        pub base_path: PathBuf,
+        pub manifest: Mutex<Option<StoredManifest>>,
+        // Unix timestamps
+        pub created_at: i64,
+        pub updated_at: Mutex<i64>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    pub struct StoredManifest {
+        pub manifest: DropManifest,
+        // Of the manifest itself, so a damaged copy never gets trusted
+        pub hash_algorithm: HashAlgorithm,
+        pub checksum: String,
+    }
 
     impl DropData {
         pub fn new(game_id: String, game_version: String, base_path: PathBuf) -> Self {
+            let now = chrono::Utc::now().timestamp();
             Self {
                 base_path,
                 game_id,
                 game_version,
                 contexts: Mutex::new(HashMap::new()),
+                manifest: Mutex::new(None),
+                created_at: now,
+                updated_at: Mutex::new(now),
+            }
+        }
+    }
+
+    // We never knew when these were made, so they start from the migration
+    impl From<v1::DropData> for DropData {
+        fn from(value: v1::DropData) -> Self {
+            let mut migrated = Self::new(value.game_id, value.game_version, value.base_path);
+            migrated.contexts = value.contexts;
+            migrated
+        }
+    }
+
+    impl From<DropData> for v1::DropData {
+        fn from(value: DropData) -> Self {
+            Self {
+                game_id: value.game_id,
+                game_version: value.game_version,
+                contexts: value.contexts,
+                base_path: value.base_path,
             }
         }
     }
@@ -49,34 +132,375 @@ impl DropData {
         let mut s = Vec::new();
         file.read_to_end(&mut s)?;
 
-        Ok(native_model::rmp_serde_1_3::RmpSerde::decode(s).unwrap())
+        match native_model::decode::<DropData>(s.clone()) {
+            Ok((dropdata, _)) => {
+                dropdata.read_progress();
+                Ok(dropdata)
+            }
+            // Written before .dropdata was versioned, so there's no header to migrate from
+            Err(e) => RmpSerde::decode(s)
+                .map(|dropdata: v1::DropData| dropdata.into())
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, e.to_string())),
+        }
     }
+    fn read_progress(&self) {
+        let Ok(raw) = fs::read(self.base_path.join(DROP_DATA_PROGRESS_PATH)) else {
+            return;
+        };
+        let progress: Result<(String, HashMap<String, bool>), _> = RmpSerde::decode(raw);
+        match progress {
+            Ok((version, contexts)) if version == self.game_version => {
+                *self.contexts.lock().unwrap() = contexts;
+            }
+            // Left over from a different version, which we no longer care about
+            Ok(_) => {}
+            Err(e) => warn!("ignoring unreadable progress for {}: {e}", self.game_id),
+        }
+    }
+    /// Never leaves a partially written .dropdata behind, even if we crash halfway through
     pub fn write(&self) {
-        let manifest_raw = match native_model::rmp_serde_1_3::RmpSerde::encode(&self) {
+        let _write_lock = WRITE_LOCK.lock().unwrap();
+        *self.updated_at.lock().unwrap() = Utc::now().timestamp();
+
+        // This has everything the checkpoints did. Losing them if we crash
+        // before the rename only means downloading those chunks again.
+        let progress_path = self.base_path.join(DROP_DATA_PROGRESS_PATH);
+        if progress_path.exists()
+            && let Err(e) = fs::remove_file(&progress_path)
+        {
+            error!("failed to remove {}: {e}", progress_path.display());
+            return;
+        }
+
+        let manifest_raw = match native_model::encode(self) {
             Ok(data) => data,
-            Err(_) => return,
+            Err(e) => {
+                error!(
+                    "failed to encode {}: {e}",
+                    self.base_path.join(DROP_DATA_PATH).display()
+                );
+                return;
+            }
         };
 
-        let mut file = match File::create(self.base_path.join(DROP_DATA_PATH)) {
-            Ok(file) => file,
+        if let Err(e) = self.write_atomically(&manifest_raw, DROP_DATA_PATH, DROP_DATA_TEMP_PATH) {
+            error!(
+                "failed to write {}: {e}",
+                self.base_path.join(DROP_DATA_PATH).display()
+            );
+        }
+    }
+    /// Only saves the contexts, so it's cheap enough to do mid-download.
+    /// Needs a full `write` first, for everything else.
+    pub fn write_progress(&self) {
+        let _write_lock = WRITE_LOCK.lock().unwrap();
+
+        let progress = (&self.game_version, &*self.contexts.lock().unwrap());
+        let progress_raw = match RmpSerde::encode(&progress) {
+            Ok(data) => data,
             Err(e) => {
-                error!("{e}");
+                error!("failed to encode progress of {}: {e}", self.game_id);
                 return;
             }
         };
 
-        match file.write_all(&manifest_raw) {
-            Ok(()) => {}
-            Err(e) => error!("{e}"),
+        if let Err(e) = self.write_atomically(
+            &progress_raw,
+            DROP_DATA_PROGRESS_PATH,
+            DROP_DATA_PROGRESS_TEMP_PATH,
+        ) {
+            error!(
+                "failed to write {}: {e}",
+                self.base_path.join(DROP_DATA_PROGRESS_PATH).display()
+            );
         }
     }
+    fn write_atomically(&self, data: &[u8], path: &str, temp_path: &str) -> io::Result<()> {
+        let temp_path = self.base_path.join(temp_path);
+
+        let mut file = File::create(&temp_path)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+        drop(file);
+
+        rename(&temp_path, self.base_path.join(path))
+    }
     pub fn set_contexts(&self, completed_contexts: &[(String, bool)]) {
-        *self.contexts.lock().unwrap() = completed_contexts.iter().map(|s| (s.0.clone(), s.1)).collect();
+        *self.contexts.lock().unwrap() = completed_contexts
+            .iter()
+            .map(|s| (s.0.clone(), s.1))
+            .collect();
     }
     pub fn set_context(&self, context: String, state: bool) {
-        self.contexts.lock().unwrap().entry(context).insert_entry(state);
+        self.contexts
+            .lock()
+            .unwrap()
+            .entry(context)
+            .insert_entry(state);
     }
     pub fn get_contexts(&self) -> HashMap<String, bool> {
         self.contexts.lock().unwrap().clone()
     }
+    /// Keeps a copy of the manifest being installed, saved on the next write
+    pub fn set_manifest(&self, manifest: &DropManifest) {
+        let hash_algorithm = HashAlgorithm::Blake3;
+        *self.manifest.lock().unwrap() = Some(v2::StoredManifest {
+            manifest: manifest.clone(),
+            hash_algorithm,
+            checksum: manifest_checksum(manifest, hash_algorithm),
+        });
+    }
+    /// The stored copy of the manifest, as long as it's intact
+    pub fn get_manifest(&self) -> Option<DropManifest> {
+        let stored = self.manifest.lock().unwrap().clone()?;
+        if manifest_checksum(&stored.manifest, stored.hash_algorithm) != stored.checksum {
+            warn!(
+                "stored manifest for {} ({}) failed its checksum, ignoring it",
+                self.game_id, self.game_version
+            );
+            return None;
+        }
+        Some(stored.manifest)
+    }
+}
+
+/// The manifest stored alongside an install of `version`, if there is one
+pub fn installed_manifest(install_dir: &Path, version: &str) -> Option<DropManifest> {
+    DropData::read(install_dir)
+        .ok()
+        .filter(|dropdata| dropdata.game_version == version)?
+        .get_manifest()
+}
+
+// Walks files in a fixed order, so the same manifest always hashes the same
+fn manifest_checksum(manifest: &DropManifest, hash_algorithm: HashAlgorithm) -> String {
+    let mut paths = manifest.keys().collect::<Vec<_>>();
+    paths.sort();
+
+    let mut hasher = ChunkHasher::new(hash_algorithm);
+    for path in paths {
+        let chunk = &manifest[path];
+        hasher.update(path.as_bytes());
+        hasher.update(&chunk.permissions.to_le_bytes());
+        hasher.update(chunk.version_name.as_bytes());
+        hasher.update(format!("{:?}", chunk.hash_algorithm).as_bytes());
+        for (checksum, length) in chunk.checksums.iter().zip(chunk.lengths.iter()) {
+            hasher.update(checksum.as_bytes());
+            hasher.update(&length.to_le_bytes());
+        }
+    }
+    hasher.finalize()
+}
+
+/// Writes a `DropData`'s progress every so often while downloading, so a
+/// crash only loses the chunks completed since the last checkpoint
+pub struct DropDataCheckpoint {
+    last_write: Mutex<Instant>,
+    unwritten_bytes: AtomicUsize,
+}
+impl Default for DropDataCheckpoint {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl DropDataCheckpoint {
+    pub fn new() -> Self {
+        Self {
+            last_write: Mutex::new(Instant::now()),
+            unwritten_bytes: AtomicUsize::new(0),
+        }
+    }
+    /// Call once `bytes` more have been marked complete in `dropdata`
+    pub fn record(&self, dropdata: &DropData, bytes: usize) {
+        let unwritten_bytes = self.unwritten_bytes.fetch_add(bytes, Ordering::AcqRel) + bytes;
+
+        // Another thread is already writing one
+        let Ok(mut last_write) = self.last_write.try_lock() else {
+            return;
+        };
+        if unwritten_bytes < CHECKPOINT_BYTES && last_write.elapsed() < CHECKPOINT_INTERVAL {
+            return;
+        }
+
+        self.unwritten_bytes.store(0, Ordering::Release);
+        dropdata.write_progress();
+        *last_write = Instant::now();
+        debug!("checkpointed progress of {}", dropdata.game_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, fs};
+
+    use native_model::Encode;
+
+    use super::*;
+    use crate::games::downloads::manifest::tests::chunk;
+
+    fn manifest() -> DropManifest {
+        HashMap::from([
+            ("game.exe".to_owned(), chunk(&["a", "b"], &[100, 50])),
+            ("data/level.pak".to_owned(), chunk(&["c"], &[20])),
+        ])
+    }
+
+    #[test]
+    fn reads_and_migrates_unversioned_v1() {
+        let dir = tempfile::tempdir().unwrap();
+        let v1 = v1::DropData::from(DropData::new(
+            "game".to_owned(),
+            "1.0".to_owned(),
+            dir.path().to_path_buf(),
+        ));
+        v1.contexts.lock().unwrap().insert("a".to_owned(), true);
+        v1.contexts.lock().unwrap().insert("b".to_owned(), false);
+        fs::write(
+            dir.path().join(DROP_DATA_PATH),
+            native_model::rmp_serde_1_3::RmpSerde::encode(&v1).unwrap(),
+        )
+        .unwrap();
+
+        let dropdata = DropData::read(dir.path()).unwrap();
+        assert_eq!(dropdata.game_id, "game");
+        assert_eq!(dropdata.game_version, "1.0");
+        assert_eq!(dropdata.base_path, dir.path());
+        assert_eq!(
+            dropdata.get_contexts(),
+            HashMap::from([("a".to_owned(), true), ("b".to_owned(), false)])
+        );
+        assert!(dropdata.get_manifest().is_none());
+
+        // Saved again as v2, with its progress intact
+        dropdata.write();
+        let raw = fs::read(dir.path().join(DROP_DATA_PATH)).unwrap();
+        let (rewritten, version) = native_model::decode::<DropData>(raw).unwrap();
+        assert_eq!(version, 2);
+        assert_eq!(rewritten.get_contexts(), dropdata.get_contexts());
+        assert!(!dir.path().join(DROP_DATA_TEMP_PATH).exists());
+    }
+
+    #[test]
+    fn round_trips_stored_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        let dropdata = DropData::new(
+            "game".to_owned(),
+            "1.0".to_owned(),
+            dir.path().to_path_buf(),
+        );
+        dropdata.set_manifest(&manifest());
+        dropdata.write();
+
+        assert_eq!(
+            DropData::read(dir.path()).unwrap().get_manifest(),
+            Some(manifest())
+        );
+        assert_eq!(installed_manifest(dir.path(), "1.0"), Some(manifest()));
+        assert_eq!(installed_manifest(dir.path(), "2.0"), None);
+    }
+
+    #[test]
+    fn checkpoints_progress_until_next_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let dropdata = DropData::new(
+            "game".to_owned(),
+            "1.0".to_owned(),
+            dir.path().to_path_buf(),
+        );
+        dropdata.set_manifest(&manifest());
+        dropdata.set_contexts(&[("a".to_owned(), false), ("b".to_owned(), false)]);
+        dropdata.write();
+
+        dropdata.set_context("a".to_owned(), true);
+        dropdata.write_progress();
+        let resumed = DropData::read(dir.path()).unwrap();
+        assert_eq!(resumed.get_contexts(), dropdata.get_contexts());
+        assert_eq!(resumed.get_manifest(), Some(manifest()));
+
+        dropdata.set_context("b".to_owned(), true);
+        dropdata.write();
+        assert!(!dir.path().join(DROP_DATA_PROGRESS_PATH).exists());
+        assert_eq!(
+            DropData::read(dir.path()).unwrap().get_contexts(),
+            dropdata.get_contexts()
+        );
+    }
+
+    #[test]
+    fn ignores_progress_of_other_versions() {
+        let dir = tempfile::tempdir().unwrap();
+        let old = DropData::new(
+            "game".to_owned(),
+            "1.0".to_owned(),
+            dir.path().to_path_buf(),
+        );
+        old.set_context("a".to_owned(), true);
+        old.write_progress();
+
+        let dropdata = DropData::new(
+            "game".to_owned(),
+            "2.0".to_owned(),
+            dir.path().to_path_buf(),
+        );
+        fs::write(
+            dir.path().join(DROP_DATA_PATH),
+            native_model::encode(&dropdata).unwrap(),
+        )
+        .unwrap();
+
+        assert!(
+            DropData::read(dir.path())
+                .unwrap()
+                .get_contexts()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(DROP_DATA_PATH), b"not a dropdata").unwrap();
+
+        let e = DropData::read(dir.path()).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn manifest_checksum_is_stable() {
+        let mut reordered = DropManifest::new();
+        let mut entries = manifest().into_iter().collect::<Vec<_>>();
+        entries.reverse();
+        for (path, chunk) in entries {
+            reordered.insert(path, chunk);
+        }
+        assert_eq!(
+            manifest_checksum(&manifest(), HashAlgorithm::Blake3),
+            manifest_checksum(&reordered, HashAlgorithm::Blake3)
+        );
+
+        let mut resized = manifest();
+        resized.get_mut("game.exe").unwrap().lengths[1] = 51;
+        assert_ne!(
+            manifest_checksum(&manifest(), HashAlgorithm::Blake3),
+            manifest_checksum(&resized, HashAlgorithm::Blake3)
+        );
+    }
+
+    #[test]
+    fn ignores_tampered_manifest() {
+        let dropdata = DropData::new("game".to_owned(), "1.0".to_owned(), PathBuf::new());
+        dropdata.set_manifest(&manifest());
+        dropdata
+            .manifest
+            .lock()
+            .unwrap()
+            .as_mut()
+            .unwrap()
+            .manifest
+            .get_mut("data/level.pak")
+            .unwrap()
+            .checksums[0] = "d".to_owned();
+
+        assert!(dropdata.get_manifest().is_none());
+    }
 }
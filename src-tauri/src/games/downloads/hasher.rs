@@ -0,0 +1,78 @@
+use std::io::{self, Write};
+
+use bitcode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Declared per file in the manifest. Manifests from before this
+/// existed are all MD5.
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    Ord,
+    PartialOrd,
+    Eq,
+    PartialEq,
+    Hash,
+    Encode,
+    Decode,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    #[default]
+    Md5,
+    Sha256,
+    Blake3,
+}
+
+/// Used by both the download and validate paths, so a chunk is
+/// always checked with the algorithm it was published with
+pub enum ChunkHasher {
+    Md5(md5::Context),
+    Sha256(Sha256),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl ChunkHasher {
+    pub fn new(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Md5 => ChunkHasher::Md5(md5::Context::new()),
+            HashAlgorithm::Sha256 => ChunkHasher::Sha256(Sha256::new()),
+            HashAlgorithm::Blake3 => ChunkHasher::Blake3(Box::new(blake3::Hasher::new())),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            ChunkHasher::Md5(context) => context.consume(data),
+            ChunkHasher::Sha256(hasher) => hasher.update(data),
+            ChunkHasher::Blake3(hasher) => {
+                hasher.update(data);
+            }
+        }
+    }
+
+    /// Hex encoded, to match the checksums in the manifest
+    pub fn finalize(self) -> String {
+        match self {
+            ChunkHasher::Md5(context) => hex::encode(context.compute().0),
+            ChunkHasher::Sha256(hasher) => hex::encode(hasher.finalize()),
+            ChunkHasher::Blake3(hasher) => hasher.finalize().to_hex().to_string(),
+        }
+    }
+}
+
+impl Write for ChunkHasher {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -1,6 +1,17 @@
+use bitcode::{Decode, Encode};
+use log::warn;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+use crate::error::application_download_error::ApplicationDownloadError;
+use crate::error::remote_access_error::RemoteAccessError;
+use crate::games::downloads::drop_data::installed_manifest;
+use crate::games::downloads::hasher::HashAlgorithm;
+use crate::remote::auth::generate_authorization_header;
+use crate::remote::cache::{cache_object, get_cached_object};
+use crate::remote::requests::generate_url;
+use crate::remote::utils::DROP_CLIENT_ASYNC;
 
 #[derive(Debug, Clone)]
 // Drops go in buckets
@@ -12,6 +23,9 @@ pub struct DownloadDrop {
     pub length: usize,
     pub checksum: String,
     pub permissions: u32,
+    // Bytes of this drop already on disk from an earlier, failed attempt
+    pub resume_offset: usize,
+    pub hash_algorithm: HashAlgorithm,
 }
 
 #[derive(Debug, Clone)]
@@ -31,6 +45,12 @@ pub struct DownloadContext {
 pub struct ChunkBodyFile {
     filename: String,
     chunk_index: usize,
+    #[serde(skip_serializing_if = "is_zero")]
+    offset: usize,
+}
+
+fn is_zero(value: &usize) -> bool {
+    *value == 0
 }
 
 #[derive(Serialize)]
@@ -55,6 +75,7 @@ impl ChunkBody {
                 .map(|e| ChunkBodyFile {
                     filename: e.filename.clone(),
                     chunk_index: e.index,
+                    offset: e.resume_offset,
                 })
                 .collect(),
         }
@@ -62,7 +83,7 @@ impl ChunkBody {
 }
 
 pub type DropManifest = HashMap<String, DropChunk>;
-#[derive(Serialize, Deserialize, Debug, Clone, Ord, PartialOrd, Eq, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, Ord, PartialOrd, Eq, PartialEq, Encode, Decode)]
 #[serde(rename_all = "camelCase")]
 pub struct DropChunk {
     pub permissions: u32,
@@ -70,6 +91,8 @@ pub struct DropChunk {
     pub checksums: Vec<String>,
     pub lengths: Vec<usize>,
     pub version_name: String,
+    #[serde(default)]
+    pub hash_algorithm: HashAlgorithm,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -79,6 +102,8 @@ pub struct DropValidateContext {
     pub path: PathBuf,
     pub checksum: String,
     pub length: usize,
+    #[serde(default)]
+    pub hash_algorithm: HashAlgorithm,
 }
 
 impl From<DownloadBucket> for Vec<DropValidateContext> {
@@ -92,7 +117,167 @@ impl From<DownloadBucket> for Vec<DropValidateContext> {
                 path: e.path,
                 checksum: e.checksum,
                 length: e.length,
+                hash_algorithm: e.hash_algorithm,
             })
             .collect()
     }
 }
+
+#[derive(Debug, Clone)]
+pub struct ChunkLocation {
+    pub path: PathBuf,
+    pub offset: usize,
+    pub length: usize,
+    pub hash_algorithm: HashAlgorithm,
+}
+
+/// Where every chunk of a manifest lives once installed to `install_dir`,
+/// keyed by checksum
+pub fn chunk_index(manifest: &DropManifest, install_dir: &Path) -> HashMap<String, ChunkLocation> {
+    let mut index = HashMap::new();
+    for (raw_path, chunk) in manifest {
+        let path = install_dir.join(raw_path);
+        let mut offset = 0;
+        for (checksum, length) in chunk.checksums.iter().zip(chunk.lengths.iter()) {
+            index.entry(checksum.clone()).or_insert(ChunkLocation {
+                path: path.clone(),
+                offset,
+                length: *length,
+                hash_algorithm: chunk.hash_algorithm,
+            });
+            offset += *length;
+        }
+    }
+    index
+}
+
+/// Every chunk of a manifest installed to `install_dir`, for checking
+/// files that didn't come from a download
+pub fn validate_contexts(manifest: &DropManifest, install_dir: &Path) -> Vec<DropValidateContext> {
+    let mut contexts = Vec::new();
+    for (raw_path, chunk) in manifest {
+        let path = install_dir.join(raw_path);
+        let mut offset = 0;
+        for (index, (checksum, length)) in
+            chunk.checksums.iter().zip(chunk.lengths.iter()).enumerate()
+        {
+            contexts.push(DropValidateContext {
+                index,
+                offset,
+                path: path.clone(),
+                checksum: checksum.clone(),
+                length: *length,
+                hash_algorithm: chunk.hash_algorithm,
+            });
+            offset += *length;
+        }
+    }
+    contexts
+}
+
+fn manifest_cache_key(game_id: &str, version: &str) -> String {
+    format!("manifest/{game_id}/{version}")
+}
+
+pub fn cache_manifest(
+    game_id: &str,
+    version: &str,
+    manifest: &DropManifest,
+) -> Result<(), RemoteAccessError> {
+    cache_object(&manifest_cache_key(game_id, version), manifest)
+}
+
+pub fn get_cached_manifest(
+    game_id: &str,
+    version: &str,
+) -> Result<DropManifest, RemoteAccessError> {
+    get_cached_object::<DropManifest>(&manifest_cache_key(game_id, version))
+}
+
+/// Always hits the server, and refreshes the local copy of the manifest
+pub async fn fetch_manifest(
+    game_id: &str,
+    version: &str,
+) -> Result<DropManifest, ApplicationDownloadError> {
+    let url = generate_url(
+        &["/api/v1/client/game/manifest"],
+        &[("id", game_id), ("version", version)],
+    )
+    .map_err(ApplicationDownloadError::Communication)?;
+
+    let response = DROP_CLIENT_ASYNC
+        .get(url)
+        .header("Authorization", generate_authorization_header())
+        .send()
+        .await
+        .map_err(|e| ApplicationDownloadError::Communication(e.into()))?;
+
+    if response.status() != 200 {
+        return Err(ApplicationDownloadError::Communication(
+            RemoteAccessError::ManifestDownloadFailed(
+                response.status(),
+                response.text().await.unwrap(),
+            ),
+        ));
+    }
+
+    let manifest: DropManifest = response
+        .json()
+        .await
+        .map_err(|e| ApplicationDownloadError::Communication(e.into()))?;
+
+    if let Err(e) = cache_manifest(game_id, version, &manifest) {
+        warn!("failed to cache manifest for {game_id} ({version}): {e}");
+    }
+
+    Ok(manifest)
+}
+
+/// Prefers the local copy, as a version's manifest never changes
+pub async fn get_manifest(
+    game_id: &str,
+    version: &str,
+) -> Result<DropManifest, ApplicationDownloadError> {
+    if let Ok(manifest) = get_cached_manifest(game_id, version) {
+        return Ok(manifest);
+    }
+
+    fetch_manifest(game_id, version).await
+}
+
+/// Prefers the copy kept in an install's .dropdata, so an installed
+/// version can be checked or updated from without the server
+pub async fn get_installed_manifest(
+    game_id: &str,
+    version: &str,
+    install_dir: &Path,
+) -> Result<DropManifest, ApplicationDownloadError> {
+    if let Some(manifest) = installed_manifest(install_dir, version) {
+        return Ok(manifest);
+    }
+
+    get_manifest(game_id, version).await
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    /// Checksums double as chunk IDs, which is all the tests need
+    pub fn chunk(checksums: &[&str], lengths: &[usize]) -> DropChunk {
+        DropChunk {
+            permissions: 0o644,
+            ids: checksums
+                .iter()
+                .map(|checksum| checksum.to_string())
+                .collect(),
+            checksums: checksums
+                .iter()
+                .map(|checksum| checksum.to_string())
+                .collect(),
+            lengths: lengths.to_vec(),
+            version_name: "1.0".to_owned(),
+            hash_algorithm: HashAlgorithm::Md5,
+        }
+    }
+}
@@ -1,6 +1,13 @@
+mod bucket_queue;
 pub mod commands;
+pub mod delta;
 pub mod download_agent;
 mod download_logic;
 pub mod drop_data;
-mod manifest;
+pub mod hasher;
+pub mod manifest;
+pub mod move_agent;
+pub mod plan;
+pub mod restore;
+pub mod stale;
 pub mod validate;
@@ -0,0 +1,567 @@
+use std::{
+    collections::HashSet,
+    fs::{self, File, create_dir_all, remove_dir_all, set_permissions},
+    io::{self, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, mpsc::Sender},
+};
+
+use log::{info, warn};
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use tauri::{AppHandle, Emitter};
+use walkdir::WalkDir;
+
+use crate::{
+    database::{
+        db::{borrow_db_checked, borrow_db_mut_checked},
+        models::data::{
+            ApplicationTransientStatus, Database, DownloadType, DownloadableMetadata,
+            GameDownloadStatus, GameVersion, QueuedDownload, QueuedDownloadKind,
+        },
+    },
+    download_manager::{
+        download_manager_frontend::{DownloadManagerSignal, DownloadStatus},
+        downloadable::Downloadable,
+        util::{
+            download_thread_control_flag::{DownloadThreadControl, DownloadThreadControlFlag},
+            progress_object::{ProgressHandle, ProgressObject},
+        },
+    },
+    error::application_download_error::ApplicationDownloadError,
+    games::{
+        dlc::{dlc_install_dir, installed_dlc},
+        library::push_game_update,
+        mods::{installed_mods, mod_owned_files, mod_store_dir},
+        state::GameStatusManager,
+    },
+    process::utils::get_disk_available,
+};
+
+use super::{
+    drop_data::DropData,
+    manifest::{DropManifest, get_installed_manifest, validate_contexts},
+    stale::installed_game,
+    validate::chunk_matches,
+};
+
+static COPY_BUFFER_SIZE: usize = 1024 * 1024;
+
+/// Moves an installed game to another install directory. Renames when it
+/// can, otherwise copies, checks the copy against the manifest, and only
+/// then deletes the original.
+pub struct GameMoveAgent {
+    id: String,
+    version: String,
+    source: PathBuf,
+    target: PathBuf,
+    manifest: DropManifest,
+    control_flag: DownloadThreadControl,
+    progress: Arc<ProgressObject>,
+    status: Mutex<DownloadStatus>,
+    // A rename leaves nothing to check or clean up
+    renamed: Mutex<bool>,
+    // Whatever's at the target is ours to delete once this is set
+    copying: Mutex<bool>,
+}
+
+impl GameMoveAgent {
+    pub async fn new(
+        id: String,
+        target_install_dir: usize,
+        sender: Sender<DownloadManagerSignal>,
+    ) -> Result<Self, ApplicationDownloadError> {
+        let target_base = borrow_db_checked()
+            .applications
+            .install_dirs
+            .get(target_install_dir)
+            .cloned()
+            .ok_or_else(|| {
+                ApplicationDownloadError::InvalidMove(format!(
+                    "install directory {target_install_dir} doesn't exist"
+                ))
+            })?;
+
+        Self::with_target_base(id, target_base, sender).await
+    }
+
+    async fn with_target_base(
+        id: String,
+        target_base: PathBuf,
+        sender: Sender<DownloadManagerSignal>,
+    ) -> Result<Self, ApplicationDownloadError> {
+        let (version, source) = installed_game(&id)
+            .ok_or_else(|| ApplicationDownloadError::NotInstalled(id.clone()))?;
+
+        let target = target_base.join(&id);
+        if target == source {
+            return Err(ApplicationDownloadError::InvalidMove(
+                "it's already in that install directory".to_owned(),
+            ));
+        }
+        if target.exists() {
+            return Err(ApplicationDownloadError::InvalidMove(format!(
+                "{} already exists",
+                target.display()
+            )));
+        }
+
+        let manifest = get_installed_manifest(&id, &version, &source).await?;
+
+        Ok(Self::with_manifest(
+            id, version, source, target, manifest, sender,
+        ))
+    }
+
+    fn with_manifest(
+        id: String,
+        version: String,
+        source: PathBuf,
+        target: PathBuf,
+        manifest: DropManifest,
+        sender: Sender<DownloadManagerSignal>,
+    ) -> Self {
+        let size = manifest_size(&manifest);
+        Self {
+            id,
+            version,
+            source,
+            target,
+            manifest,
+            // Don't run by default
+            control_flag: DownloadThreadControl::new(DownloadThreadControlFlag::Stop),
+            progress: Arc::new(ProgressObject::new(size, 1, sender)),
+            status: Mutex::new(DownloadStatus::Queued),
+            renamed: Mutex::new(false),
+            copying: Mutex::new(false),
+        }
+    }
+
+    /// Picks a move back up after a restart. The database points at the
+    /// source until a move completes, so if that's gone the game is already
+    /// at the target, and only the bookkeeping is left to finish. Otherwise
+    /// whatever got copied is thrown away and the move starts over.
+    pub async fn resume(
+        id: String,
+        target_base: PathBuf,
+        sender: Sender<DownloadManagerSignal>,
+    ) -> Result<Self, ApplicationDownloadError> {
+        let (version, source) = installed_game(&id)
+            .ok_or_else(|| ApplicationDownloadError::NotInstalled(id.clone()))?;
+        let target = target_base.join(&id);
+
+        if target == source || (!source.exists() && target.exists()) {
+            info!("finishing move of {id} to {}", target.display());
+            let manifest = get_installed_manifest(&id, &version, &target).await?;
+            let agent = Self::with_manifest(id, version, source, target, manifest, sender);
+            agent.mark_renamed();
+            return Ok(agent);
+        }
+
+        if target.exists() {
+            info!("removing partial copy at {}", target.display());
+            remove_dir_all(&target).map_err(|e| ApplicationDownloadError::IoError(Arc::new(e)))?;
+        }
+
+        Self::with_target_base(id, target_base, sender).await
+    }
+
+    /// The game being moved, which its status is kept under
+    fn game_metadata(&self) -> DownloadableMetadata {
+        DownloadableMetadata::new(
+            self.id.clone(),
+            Some(self.version.clone()),
+            DownloadType::Game,
+        )
+    }
+
+    fn set_moving(&self, app_handle: &AppHandle) -> Result<(), ApplicationDownloadError> {
+        let mut db_lock = borrow_db_mut_checked();
+        if let Some(ApplicationTransientStatus::Running {}) = db_lock
+            .applications
+            .transient_statuses
+            .get(&self.game_metadata())
+        {
+            return Err(ApplicationDownloadError::InvalidMove(
+                "it's currently running".to_owned(),
+            ));
+        }
+
+        let status = ApplicationTransientStatus::Moving {};
+        db_lock
+            .applications
+            .transient_statuses
+            .insert(self.game_metadata(), status.clone());
+        push_game_update(app_handle, &self.id, None, (None, Some(status)));
+
+        Ok(())
+    }
+
+    fn reset_progress(&self, max: usize) {
+        self.progress.set_max(max);
+        self.progress.set_size(1);
+        self.progress.reset();
+    }
+
+    /// Nothing is left to copy, so there's nothing to check or clean up either
+    fn mark_renamed(&self) {
+        *self.renamed.lock().unwrap() = true;
+        let size = manifest_size(&self.manifest);
+        self.reset_progress(size);
+        self.progress_handle().add(size);
+    }
+
+    fn progress_handle(&self) -> ProgressHandle {
+        ProgressHandle::new(self.progress.get(0), self.progress.clone())
+    }
+
+    fn move_files(&self) -> Result<bool, ApplicationDownloadError> {
+        if let Some(parent) = self.target.parent() {
+            create_dir_all(parent).map_err(|e| ApplicationDownloadError::IoError(Arc::new(e)))?;
+        }
+
+        match fs::rename(&self.source, &self.target) {
+            Ok(()) => {
+                info!(
+                    "renamed {} to {}",
+                    self.source.display(),
+                    self.target.display()
+                );
+                self.mark_renamed();
+                return Ok(true);
+            }
+            Err(e) if e.kind() == ErrorKind::CrossesDevices => {}
+            Err(e) => return Err(ApplicationDownloadError::IoError(Arc::new(e))),
+        }
+
+        let required_space = manifest_size(&self.manifest);
+        let target_base = self.target.parent().unwrap().to_path_buf();
+        let available_space = get_disk_available(target_base)?;
+        if required_space as u64 > available_space {
+            return Err(ApplicationDownloadError::DiskFull(
+                required_space as u64,
+                available_space,
+            ));
+        }
+
+        info!(
+            "copying {} to {} across filesystems",
+            self.source.display(),
+            self.target.display()
+        );
+        *self.copying.lock().unwrap() = true;
+        self.copy_files()
+            .map_err(|e| ApplicationDownloadError::IoError(Arc::new(e)))
+    }
+
+    /// Returns false if stopped part way through
+    fn copy_files(&self) -> io::Result<bool> {
+        let entries = WalkDir::new(&self.source)
+            .into_iter()
+            .collect::<Result<Vec<_>, walkdir::Error>>()
+            .map_err(io::Error::other)?;
+
+        let mut total_size = 0;
+        for entry in &entries {
+            if entry.file_type().is_file() {
+                total_size += entry.metadata().map_err(io::Error::other)?.len() as usize;
+            }
+        }
+        self.reset_progress(total_size);
+        let progress = self.progress_handle();
+
+        let mut buffer = vec![0u8; COPY_BUFFER_SIZE];
+        for entry in entries {
+            let relative = entry.path().strip_prefix(&self.source).unwrap();
+            let target = self.target.join(relative);
+            let file_type = entry.file_type();
+
+            if file_type.is_dir() {
+                create_dir_all(&target)?;
+            } else if file_type.is_symlink() {
+                #[cfg(unix)]
+                std::os::unix::fs::symlink(fs::read_link(entry.path())?, &target)?;
+                #[cfg(not(unix))]
+                warn!("skipping symlink {}", entry.path().display());
+            } else if !copy_file(
+                entry.path(),
+                &target,
+                &mut buffer,
+                &self.control_flag,
+                &progress,
+            )? {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Checks the copy against the manifest before anything gets deleted
+    fn check_copy(&self, app_handle: &AppHandle) -> Result<bool, ApplicationDownloadError> {
+        *self.status.lock().unwrap() = DownloadStatus::Validating;
+
+        // Mods replaced some of the game's files, those won't match
+        let mod_owned: HashSet<PathBuf> = mod_owned_files(&borrow_db_checked(), &self.id)
+            .into_iter()
+            .map(|relative_path| self.target.join(relative_path))
+            .collect();
+        let contexts: Vec<_> = validate_contexts(&self.manifest, &self.target)
+            .into_iter()
+            .filter(|context| !mod_owned.contains(&context.path))
+            .collect();
+        self.reset_progress(contexts.iter().map(|context| context.length).sum());
+        let progress = self.progress_handle();
+
+        let invalid = contexts
+            .par_iter()
+            .filter(|context| {
+                if self.control_flag.get() == DownloadThreadControlFlag::Stop {
+                    return false;
+                }
+                let matches = chunk_matches(context).unwrap_or(false);
+                progress.add(context.length);
+                !matches
+            })
+            .count();
+
+        if self.control_flag.get() == DownloadThreadControlFlag::Stop {
+            return Ok(false);
+        }
+        if invalid != 0 {
+            warn!("{invalid} chunks of {} didn't survive the copy", self.id);
+            app_handle
+                .emit("download_error", format!("{invalid} chunks failed to copy"))
+                .unwrap();
+            return Err(ApplicationDownloadError::Checksum);
+        }
+
+        Ok(true)
+    }
+
+    /// DLC and mods keep their own .dropdata inside the game's directory,
+    /// and those need pointing at the new location too
+    fn rebase_dropdata(&self) {
+        let mut dirs = vec![self.target.clone()];
+        {
+            let db_lock = borrow_db_checked();
+            dirs.extend(
+                installed_dlc(&db_lock, &self.id)
+                    .into_keys()
+                    .map(|dlc_id| dlc_install_dir(&self.target, &dlc_id)),
+            );
+            dirs.extend(
+                installed_mods(&db_lock, &self.id)
+                    .into_iter()
+                    .map(|installed| mod_store_dir(&self.target, &installed.mod_id)),
+            );
+        }
+
+        for dir in dirs {
+            match DropData::read(&dir) {
+                Ok(mut drop_data) => {
+                    drop_data.base_path = dir;
+                    drop_data.write();
+                }
+                Err(e) => warn!("failed to update .dropdata in {}: {e}", dir.display()),
+            }
+        }
+    }
+
+    /// Drops whatever got copied, the original is still intact
+    fn clean_up(&self, app_handle: &AppHandle) {
+        if !*self.renamed.lock().unwrap()
+            && *self.copying.lock().unwrap()
+            && self.target.exists()
+            && let Err(e) = remove_dir_all(&self.target)
+        {
+            warn!(
+                "failed to remove partial copy at {}: {e}",
+                self.target.display()
+            );
+        }
+
+        let mut db_lock = borrow_db_mut_checked();
+        db_lock
+            .applications
+            .transient_statuses
+            .remove(&self.game_metadata());
+        push_game_update(
+            app_handle,
+            &self.id,
+            installed_version(&db_lock, &self.id, &self.version),
+            GameStatusManager::fetch_state(&self.id, &db_lock),
+        );
+    }
+}
+
+/// Returns false if stopped part way through
+fn copy_file(
+    source: &Path,
+    target: &Path,
+    buffer: &mut [u8],
+    control_flag: &DownloadThreadControl,
+    progress: &ProgressHandle,
+) -> io::Result<bool> {
+    let mut source_file = File::open(source)?;
+    let mut target_file = File::create(target)?;
+    loop {
+        if control_flag.get() == DownloadThreadControlFlag::Stop {
+            return Ok(false);
+        }
+        let read = source_file.read(buffer)?;
+        if read == 0 {
+            break;
+        }
+        target_file.write_all(&buffer[..read])?;
+        progress.add(read);
+    }
+    set_permissions(target, source_file.metadata()?.permissions())?;
+    Ok(true)
+}
+
+fn manifest_size(manifest: &DropManifest) -> usize {
+    manifest
+        .values()
+        .map(|chunk| chunk.lengths.iter().sum::<usize>())
+        .sum()
+}
+
+fn installed_version(db: &Database, id: &str, version: &str) -> Option<GameVersion> {
+    db.applications.game_versions.get(id)?.get(version).cloned()
+}
+
+impl Downloadable for GameMoveAgent {
+    fn download(&self, app_handle: &AppHandle) -> Result<bool, ApplicationDownloadError> {
+        *self.status.lock().unwrap() = DownloadStatus::Downloading;
+        self.set_moving(app_handle)?;
+        if *self.renamed.lock().unwrap() {
+            return Ok(true);
+        }
+        if self.target.exists() {
+            // Appeared since this was queued, and isn't ours to delete
+            if !*self.copying.lock().unwrap() {
+                return Err(ApplicationDownloadError::InvalidMove(format!(
+                    "{} already exists",
+                    self.target.display()
+                )));
+            }
+            // Starting over is simpler than working out how far we got
+            remove_dir_all(&self.target)
+                .map_err(|e| ApplicationDownloadError::IoError(Arc::new(e)))?;
+        }
+        self.move_files()
+    }
+
+    fn validate(&self, app_handle: &AppHandle) -> Result<bool, ApplicationDownloadError> {
+        if *self.renamed.lock().unwrap() {
+            return Ok(true);
+        }
+        self.check_copy(app_handle)
+    }
+
+    fn progress(&self) -> Arc<ProgressObject> {
+        self.progress.clone()
+    }
+
+    fn control_flag(&self) -> DownloadThreadControl {
+        self.control_flag.clone()
+    }
+
+    // Disk bound, so threads don't help
+    fn set_thread_budget(&self, _threads: usize) {}
+
+    fn status(&self) -> DownloadStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    fn metadata(&self) -> DownloadableMetadata {
+        DownloadableMetadata {
+            id: self.id.clone(),
+            version: Some(self.version.clone()),
+            download_type: DownloadType::Move,
+        }
+    }
+
+    fn install_dir(&self) -> PathBuf {
+        self.target.clone()
+    }
+
+    fn queue_entry(&self) -> QueuedDownload {
+        QueuedDownload {
+            meta: self.metadata(),
+            kind: QueuedDownloadKind::Move {
+                base_dir: self.target.parent().unwrap().to_path_buf(),
+            },
+            paused: false,
+        }
+    }
+
+    fn on_initialised(&self, _app_handle: &AppHandle) {
+        *self.status.lock().unwrap() = DownloadStatus::Queued;
+    }
+
+    fn on_error(&self, app_handle: &AppHandle, error: &ApplicationDownloadError) {
+        *self.status.lock().unwrap() = DownloadStatus::Error;
+        app_handle
+            .emit("download_error", error.to_string())
+            .unwrap();
+        warn!("failed to move {}: {error}", self.id);
+        self.clean_up(app_handle);
+    }
+
+    fn on_complete(&self, app_handle: &AppHandle) {
+        let target = self.target.to_string_lossy().to_string();
+
+        self.rebase_dropdata();
+
+        let mut db_lock = borrow_db_mut_checked();
+        let status = match db_lock.applications.game_statuses.get(&self.id) {
+            Some(GameDownloadStatus::SetupRequired { version_name, .. }) => {
+                GameDownloadStatus::SetupRequired {
+                    version_name: version_name.clone(),
+                    install_dir: target,
+                }
+            }
+            _ => GameDownloadStatus::Installed {
+                version_name: self.version.clone(),
+                install_dir: target,
+            },
+        };
+        db_lock
+            .applications
+            .game_statuses
+            .insert(self.id.clone(), status);
+        db_lock
+            .applications
+            .transient_statuses
+            .remove(&self.game_metadata());
+        push_game_update(
+            app_handle,
+            &self.id,
+            installed_version(&db_lock, &self.id, &self.version),
+            GameStatusManager::fetch_state(&self.id, &db_lock),
+        );
+        drop(db_lock);
+
+        if !*self.renamed.lock().unwrap()
+            && let Err(e) = remove_dir_all(&self.source)
+        {
+            warn!(
+                "moved {}, but failed to remove the original at {}: {e}",
+                self.id,
+                self.source.display()
+            );
+        }
+
+        info!("moved {} to {}", self.id, self.target.display());
+    }
+
+    fn on_cancelled(&self, app_handle: &AppHandle) {
+        // Once renamed, the original is gone, so there's no going back
+        if *self.renamed.lock().unwrap() {
+            self.on_complete(app_handle);
+        } else {
+            self.clean_up(app_handle);
+        }
+    }
+}
@@ -0,0 +1,270 @@
+use std::{collections::HashSet, path::PathBuf};
+
+use log::warn;
+use serde::Serialize;
+
+use crate::{
+    database::{
+        db::borrow_db_checked,
+        models::data::{Database, GameDownloadStatus},
+    },
+    error::application_download_error::ApplicationDownloadError,
+    games::mods::mod_owned_files,
+    process::utils::{get_disk_available, space_needed},
+};
+
+use super::{
+    bucket_queue::BucketSizer,
+    delta::DeltaSource,
+    drop_data::DropData,
+    manifest::{DropManifest, get_installed_manifest},
+};
+
+/// What downloading a version would involve, worked out without touching disk
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DownloadPlan {
+    pub game_id: String,
+    pub version: String,
+    pub install_dir: PathBuf,
+    pub total_size: u64,
+    // Already downloaded, or reused from the installed version
+    pub present_bytes: u64,
+    pub fetch_bytes: u64,
+    // Can be more than fetch_bytes, as updates rebuild changed files alongside the old ones
+    pub required_space: u64,
+    pub free_space: Option<u64>,
+    pub fits: bool,
+    pub file_count: usize,
+    pub bucket_count: usize,
+    pub changes: Option<VersionChanges>,
+}
+
+/// How switching an installed game to another version changes its files
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct VersionChanges {
+    pub from_version: String,
+    pub added: Vec<String>,
+    pub changed: Vec<String>,
+    pub removed: Vec<String>,
+    // Files deployed by mods that the switch would overwrite or delete
+    pub modded: Vec<String>,
+}
+
+/// Where `game_id` would be downloaded to, and the version already there.
+/// Installed games are always updated in place.
+fn target_install_dir(
+    db: &Database,
+    game_id: &str,
+    install_dir_index: usize,
+) -> Result<(PathBuf, Option<String>), ApplicationDownloadError> {
+    match db.applications.game_statuses.get(game_id) {
+        Some(
+            GameDownloadStatus::Installed {
+                version_name,
+                install_dir,
+            }
+            | GameDownloadStatus::SetupRequired {
+                version_name,
+                install_dir,
+            },
+        ) => Ok((PathBuf::from(install_dir), Some(version_name.clone()))),
+        Some(GameDownloadStatus::PartiallyInstalled { install_dir, .. }) => {
+            Ok((PathBuf::from(install_dir), None))
+        }
+        _ => db
+            .applications
+            .install_dirs
+            .get(install_dir_index)
+            .map(|base_dir| (base_dir.join(game_id), None))
+            .ok_or(ApplicationDownloadError::InvalidInstallDir(
+                install_dir_index,
+            )),
+    }
+}
+
+pub async fn plan_game_download(
+    game_id: String,
+    version: String,
+    install_dir_index: usize,
+) -> Result<DownloadPlan, ApplicationDownloadError> {
+    let (install_dir, installed_version, sizer) = {
+        let db_lock = borrow_db_checked();
+        let (install_dir, installed_version) =
+            target_install_dir(&db_lock, &game_id, install_dir_index)?;
+        (
+            install_dir,
+            installed_version,
+            BucketSizer::new(&db_lock.settings),
+        )
+    };
+    let up_to_date = installed_version.as_ref() == Some(&version);
+
+    let manifest = get_installed_manifest(&game_id, &version, &install_dir).await?;
+
+    let delta = match installed_version {
+        Some(installed_version) if installed_version != version => Some(DeltaSource {
+            manifest: get_installed_manifest(&game_id, &installed_version, &install_dir).await?,
+            version: installed_version,
+            install_dir: install_dir.clone(),
+        }),
+        _ => None,
+    };
+
+    // Progress from a previous, unfinished attempt at this version
+    let completed = DropData::read(&install_dir)
+        .ok()
+        .filter(|dropdata| dropdata.game_version == version)
+        .map(|dropdata| dropdata.get_contexts())
+        .unwrap_or_default();
+
+    let unchanged = delta
+        .as_ref()
+        .map(|delta| delta.unchanged_files(&manifest))
+        .unwrap_or_default();
+    let local_chunks = delta
+        .as_ref()
+        .map(|delta| delta.chunk_index())
+        .unwrap_or_default();
+    let written_to = match &delta {
+        Some(delta) => delta.staging_dir(),
+        None => install_dir.clone(),
+    };
+
+    let mut total_size = 0;
+    let mut present_bytes = 0;
+    let mut required_space = 0;
+    let mut file_count = 0;
+    let mut to_fetch = Vec::new();
+    for (raw_path, chunk) in &manifest {
+        let file_size = chunk.lengths.iter().sum::<usize>() as u64;
+        total_size += file_size;
+        if up_to_date || unchanged.contains(raw_path) {
+            present_bytes += file_size;
+            continue;
+        }
+        file_count += 1;
+
+        let mut incomplete = 0;
+        for (checksum, length) in chunk.checksums.iter().zip(chunk.lengths.iter()) {
+            if *completed.get(checksum).unwrap_or(&false) {
+                present_bytes += *length as u64;
+                continue;
+            }
+            incomplete += *length as u64;
+            if local_chunks.contains_key(checksum) {
+                present_bytes += *length as u64;
+            } else {
+                to_fetch.push(*length);
+            }
+        }
+
+        // Same as the download agent, partially written files already hold some of it
+        required_space += space_needed(&written_to.join(raw_path), file_size, incomplete);
+    }
+
+    let free_space = get_disk_available(install_dir.clone())
+        .inspect_err(|e| warn!("couldn't check free disk space: {e}"))
+        .ok();
+
+    let changes = delta.map(|delta| version_changes(&game_id, &delta, &manifest, &unchanged));
+
+    Ok(DownloadPlan {
+        game_id,
+        version,
+        install_dir,
+        total_size,
+        present_bytes,
+        fetch_bytes: total_size - present_bytes,
+        required_space,
+        fits: free_space.is_none_or(|free_space| required_space <= free_space),
+        free_space,
+        file_count,
+        bucket_count: count_buckets(&to_fetch, sizer.bucket_size(0, 1)),
+        changes,
+    })
+}
+
+fn version_changes(
+    game_id: &str,
+    delta: &DeltaSource,
+    target: &DropManifest,
+    unchanged: &HashSet<String>,
+) -> VersionChanges {
+    let (mut added, mut changed): (Vec<String>, Vec<String>) = target
+        .keys()
+        .filter(|raw_path| !unchanged.contains(*raw_path))
+        .cloned()
+        .partition(|raw_path| !delta.manifest.contains_key(raw_path));
+    let mut removed = delta.removed_files(target);
+
+    let mod_files = mod_owned_files(&borrow_db_checked(), game_id);
+    let mut modded = changed
+        .iter()
+        .chain(removed.iter())
+        .filter(|raw_path| mod_files.contains(&PathBuf::from(raw_path)))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    added.sort();
+    changed.sort();
+    removed.sort();
+    modded.sort();
+
+    VersionChanges {
+        from_version: delta.version.clone(),
+        added,
+        changed,
+        removed,
+        modded,
+    }
+}
+
+// Mirrors how the download agent groups drops, so the count matches
+fn count_buckets(lengths: &[usize], target_bucket_size: usize) -> usize {
+    let mut buckets = 0;
+    let mut current_bucket_size = 0;
+    let mut current_bucket_drops = 0;
+    for length in lengths {
+        if *length >= target_bucket_size {
+            buckets += 1;
+            continue;
+        }
+        if current_bucket_size + *length >= target_bucket_size && current_bucket_drops > 0 {
+            buckets += 1;
+            current_bucket_size = 0;
+            current_bucket_drops = 0;
+        }
+        current_bucket_size += *length;
+        current_bucket_drops += 1;
+    }
+    if current_bucket_drops > 0 {
+        buckets += 1;
+    }
+    buckets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_drops_no_buckets() {
+        assert_eq!(count_buckets(&[], 10), 0);
+    }
+
+    #[test]
+    fn large_drops_get_their_own_bucket() {
+        assert_eq!(count_buckets(&[10, 25, 10], 10), 3);
+    }
+
+    #[test]
+    fn small_drops_share_buckets() {
+        assert_eq!(count_buckets(&[4, 4, 4], 10), 2);
+        // Filling a bucket exactly starts a new one
+        assert_eq!(count_buckets(&[5, 5], 10), 2);
+        // Large drops don't interrupt the bucket being filled
+        assert_eq!(count_buckets(&[3, 20, 3, 3], 10), 2);
+    }
+}
@@ -0,0 +1,92 @@
+use std::sync::{Arc, mpsc::Sender};
+
+use log::{info, warn};
+
+use crate::{
+    database::{
+        db::borrow_db_checked,
+        models::data::{QueuedDownload, QueuedDownloadKind},
+    },
+    download_manager::{
+        download_manager_builder::DownloadAgent,
+        download_manager_frontend::{DownloadManager, DownloadManagerSignal},
+        downloadable::Downloadable,
+    },
+    error::application_download_error::ApplicationDownloadError,
+};
+
+use super::{download_agent::GameDownloadAgent, move_agent::GameMoveAgent};
+
+/// Queues everything that was queued when we last exited, in the same
+/// order, and starts it back up unless the queue was paused. Anything
+/// that can't be rebuilt (e.g. while offline) stays stored for next time.
+pub async fn restore_download_queue(download_manager: Arc<DownloadManager>) {
+    let queue = borrow_db_checked().applications.download_queue.clone();
+    if queue.is_empty() {
+        return;
+    }
+
+    info!("restoring {} queued downloads", queue.len());
+    let sender = download_manager.get_sender();
+    let mut unrestored = Vec::new();
+    for entry in queue {
+        // Queued again by the user before we got to it
+        if download_manager.read_queue().contains(&entry.meta) {
+            continue;
+        }
+        let was_paused = entry.paused;
+        match rebuild_download(entry.clone(), sender.clone()).await {
+            Ok(download_agent) => download_manager
+                .restore_download(download_agent, was_paused)
+                .unwrap(),
+            Err(e) => {
+                warn!("couldn't restore queued download {:?}: {e}", entry.meta);
+                unrestored.push(entry);
+            }
+        }
+    }
+    download_manager.finish_restore(unrestored).unwrap();
+
+    // Read now rather than up front, in case it was paused or resumed meanwhile
+    let paused = borrow_db_checked().applications.download_queue_paused;
+
+    // Stopping an idle queue only marks it paused, so resuming or
+    // prioritising one download doesn't start all of them
+    if paused {
+        download_manager.pause_downloads();
+    } else {
+        download_manager.resume_downloads();
+    }
+}
+
+async fn rebuild_download(
+    entry: QueuedDownload,
+    sender: Sender<DownloadManagerSignal>,
+) -> Result<DownloadAgent, ApplicationDownloadError> {
+    let id = entry.meta.id;
+    let version = entry.meta.version.unwrap_or_default();
+
+    let download_agent: Box<dyn Downloadable + Send + Sync> = match entry.kind {
+        QueuedDownloadKind::Install { base_dir } => {
+            Box::new(GameDownloadAgent::new(id, version, base_dir, sender).await?)
+        }
+        QueuedDownloadKind::Update => {
+            Box::new(GameDownloadAgent::new_update(id, version, sender).await?)
+        }
+        QueuedDownloadKind::Repair => Box::new(GameDownloadAgent::new_repair(id, sender).await?),
+        QueuedDownloadKind::Move { base_dir } => {
+            Box::new(GameMoveAgent::resume(id, base_dir, sender).await?)
+        }
+        QueuedDownloadKind::Dlc { parent_id } => {
+            Box::new(GameDownloadAgent::new_dlc(parent_id, id, version, sender).await?)
+        }
+        QueuedDownloadKind::Mod { parent_id } => {
+            Box::new(GameDownloadAgent::new_mod(parent_id, id, version, sender).await?)
+        }
+        QueuedDownloadKind::Tool => {
+            Box::new(GameDownloadAgent::new_tool(id, version, sender).await?)
+        }
+    };
+
+    Ok(Arc::new(download_agent))
+}
@@ -0,0 +1,239 @@
+use std::{
+    collections::HashSet,
+    fs::{remove_dir, remove_file},
+    io,
+    path::{Component, Path, PathBuf},
+};
+
+use glob::Pattern;
+use log::{info, warn};
+use serde::Serialize;
+use tauri::{AppHandle, Emitter};
+use walkdir::WalkDir;
+
+use crate::{
+    database::{db::borrow_db_checked, models::data::GameDownloadStatus},
+    games::{
+        dlc::DLC_DIR,
+        mods::{MOD_DIR, mod_owned_files},
+    },
+};
+
+use super::{
+    delta::DELTA_STAGING_DIR,
+    drop_data::{
+        DROP_DATA_PATH, DROP_DATA_PROGRESS_PATH, DROP_DATA_PROGRESS_TEMP_PATH, DROP_DATA_TEMP_PATH,
+    },
+    manifest::DropManifest,
+};
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct StaleFilesEvent {
+    pub game_id: String,
+    pub files: Vec<String>,
+}
+
+/// The version and install directory of an installed game
+pub fn installed_game(game_id: &str) -> Option<(String, PathBuf)> {
+    match borrow_db_checked()
+        .applications
+        .game_statuses
+        .get(game_id)?
+    {
+        GameDownloadStatus::Installed {
+            version_name,
+            install_dir,
+        }
+        | GameDownloadStatus::SetupRequired {
+            version_name,
+            install_dir,
+        } => Some((version_name.clone(), PathBuf::from(install_dir))),
+        _ => None,
+    }
+}
+
+// Things we put in the install directory ourselves, that no manifest lists
+fn is_managed(relative_path: &Path) -> bool {
+    matches!(
+        relative_path.components().next(),
+        Some(Component::Normal(name)) if [
+            DROP_DATA_PATH,
+            DROP_DATA_TEMP_PATH,
+            DROP_DATA_PROGRESS_PATH,
+            DROP_DATA_PROGRESS_TEMP_PATH,
+            DELTA_STAGING_DIR,
+            MOD_DIR,
+            DLC_DIR,
+        ]
+        .iter()
+        .any(|managed| name == *managed)
+    )
+}
+
+// Manifests always use forward slashes
+fn manifest_path(relative_path: &Path) -> String {
+    relative_path
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn parse_keep_globs(globs: &[String]) -> Vec<Pattern> {
+    globs
+        .iter()
+        .filter_map(|glob| {
+            Pattern::new(glob)
+                .inspect_err(|e| warn!("ignoring invalid keep-glob {glob}: {e}"))
+                .ok()
+        })
+        .collect()
+}
+
+/// Files in a game's install directory that its manifest doesn't list,
+/// relative to it. Mod files and anything matching a keep-glob are left out.
+pub fn find_stale_files(game_id: &str, install_dir: &Path, manifest: &DropManifest) -> Vec<String> {
+    let (keep_globs, mod_files) = {
+        let db_lock = borrow_db_checked();
+        let keep_globs = parse_keep_globs(&db_lock.settings.stale_file_keep_globs);
+        (keep_globs, mod_owned_files(&db_lock, game_id))
+    };
+
+    WalkDir::new(install_dir)
+        .into_iter()
+        .filter_entry(|entry| {
+            entry
+                .path()
+                .strip_prefix(install_dir)
+                .is_ok_and(|relative_path| !is_managed(relative_path))
+        })
+        .flatten()
+        .filter(|entry| !entry.file_type().is_dir())
+        .filter_map(|entry| {
+            let relative_path = entry.path().strip_prefix(install_dir).ok()?.to_path_buf();
+            let raw_path = manifest_path(&relative_path);
+            let stale = !manifest.contains_key(&raw_path)
+                && !mod_files.contains(&relative_path)
+                && !keep_globs.iter().any(|glob| glob.matches(&raw_path));
+            stale.then_some(raw_path)
+        })
+        .collect()
+}
+
+/// Deletes `files` from the install directory, along with any directories
+/// that are left empty. Only paths that are actually stale get touched.
+pub fn remove_stale_files(
+    game_id: &str,
+    install_dir: &Path,
+    manifest: &DropManifest,
+    files: &[String],
+) -> io::Result<usize> {
+    let stale = find_stale_files(game_id, install_dir, manifest)
+        .into_iter()
+        .collect::<HashSet<_>>();
+
+    let mut removed = 0;
+    for raw_path in files.iter().filter(|raw_path| stale.contains(*raw_path)) {
+        let path = install_dir.join(raw_path);
+        remove_file(&path)?;
+        removed += 1;
+
+        // Fails, and stops, at the first directory that isn't empty
+        for container in path.ancestors().skip(1) {
+            if container == install_dir || remove_dir(container).is_err() {
+                break;
+            }
+        }
+    }
+
+    info!("removed {removed} stale files from {game_id}");
+    Ok(removed)
+}
+
+/// Run once a game's files change. Either cleans up straight away,
+/// or tells the frontend so it can offer to.
+pub fn reconcile_stale_files(
+    app_handle: &AppHandle,
+    game_id: &str,
+    install_dir: &Path,
+    manifest: &DropManifest,
+    remove: bool,
+) {
+    let files = find_stale_files(game_id, install_dir, manifest);
+    if files.is_empty() {
+        return;
+    }
+
+    if remove {
+        if let Err(e) = remove_stale_files(game_id, install_dir, manifest, &files) {
+            warn!("failed to remove stale files from {game_id}: {e}");
+        }
+        return;
+    }
+
+    info!("found {} stale files in {game_id}", files.len());
+    app_handle
+        .emit(
+            &format!("stale_files/{game_id}"),
+            StaleFilesEvent {
+                game_id: game_id.to_owned(),
+                files,
+            },
+        )
+        .unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn our_own_files_are_managed() {
+        for managed in [
+            DROP_DATA_PATH,
+            DROP_DATA_TEMP_PATH,
+            DROP_DATA_PROGRESS_PATH,
+            DROP_DATA_PROGRESS_TEMP_PATH,
+            DELTA_STAGING_DIR,
+            MOD_DIR,
+            DLC_DIR,
+        ] {
+            assert!(is_managed(Path::new(managed)));
+            assert!(is_managed(&Path::new(managed).join("nested/file")));
+        }
+    }
+
+    #[test]
+    fn game_files_are_not_managed() {
+        assert!(!is_managed(Path::new("game.exe")));
+        // Only the top level is ours
+        assert!(!is_managed(&Path::new("data").join(MOD_DIR)));
+        assert!(!is_managed(&Path::new("data").join(DROP_DATA_PATH)));
+    }
+
+    #[test]
+    fn manifest_paths_use_forward_slashes() {
+        let relative_path = Path::new("data").join("levels").join("1.pak");
+        assert_eq!(manifest_path(&relative_path), "data/levels/1.pak");
+    }
+
+    #[test]
+    fn keep_globs_match_manifest_paths() {
+        let globs = parse_keep_globs(&["saves/**".to_owned(), "*.cfg".to_owned()]);
+        let kept = |raw_path: &str| globs.iter().any(|glob| glob.matches(raw_path));
+
+        assert!(kept("saves/slot1/data.sav"));
+        assert!(kept("settings.cfg"));
+        assert!(kept("config/input.cfg"));
+        assert!(!kept("game.exe"));
+        assert!(!kept("old/saves/slot1.sav"));
+    }
+
+    #[test]
+    fn invalid_keep_globs_are_skipped() {
+        let globs = parse_keep_globs(&["[".to_owned(), "*.log".to_owned()]);
+        assert_eq!(globs.len(), 1);
+        assert!(globs[0].matches("crash.log"));
+    }
+}
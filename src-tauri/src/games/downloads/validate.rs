@@ -4,7 +4,6 @@ use std::{
 };
 
 use log::debug;
-use md5::Context;
 
 use crate::{
     download_manager::util::{
@@ -12,7 +11,7 @@ use crate::{
         progress_object::ProgressHandle,
     },
     error::application_download_error::ApplicationDownloadError,
-    games::downloads::manifest::DropValidateContext,
+    games::downloads::{hasher::ChunkHasher, manifest::DropValidateContext},
 };
 
 pub fn validate_game_chunk(
@@ -22,7 +21,10 @@ pub fn validate_game_chunk(
 ) -> Result<bool, ApplicationDownloadError> {
     debug!(
         "Starting chunk validation {}, {}, {} #{}",
-        ctx.path.display(), ctx.index, ctx.offset, ctx.checksum
+        ctx.path.display(),
+        ctx.index,
+        ctx.offset,
+        ctx.checksum
     );
     // If we're paused
     if control_flag.get() == DownloadThreadControlFlag::Stop {
@@ -40,7 +42,7 @@ pub fn validate_game_chunk(
             .expect("Failed to seek to file offset");
     }
 
-    let mut hasher = md5::Context::new();
+    let mut hasher = ChunkHasher::new(ctx.hash_algorithm);
 
     let completed =
         validate_copy(&mut source, &mut hasher, ctx.length, control_flag, progress).unwrap();
@@ -48,7 +50,7 @@ pub fn validate_game_chunk(
         return Ok(false);
     }
 
-    let res = hex::encode(hasher.compute().0);
+    let res = hasher.finalize();
     if res != ctx.checksum {
         return Ok(false);
     }
@@ -61,9 +63,20 @@ pub fn validate_game_chunk(
     Ok(true)
 }
 
+/// Checks a chunk on disk without reporting progress
+pub fn chunk_matches(ctx: &DropValidateContext) -> io::Result<bool> {
+    let mut source = File::open(&ctx.path)?;
+    source.seek(SeekFrom::Start(ctx.offset as u64))?;
+
+    let mut hasher = ChunkHasher::new(ctx.hash_algorithm);
+    let copied = io::copy(&mut source.take(ctx.length as u64), &mut hasher)?;
+
+    Ok(copied as usize == ctx.length && hasher.finalize() == ctx.checksum)
+}
+
 fn validate_copy(
     source: &mut File,
-    dest: &mut Context,
+    dest: &mut ChunkHasher,
     size: usize,
     control_flag: &DownloadThreadControl,
     progress: ProgressHandle,
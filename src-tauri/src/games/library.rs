@@ -48,6 +48,11 @@ pub struct Game {
     m_image_library_object_ids: Vec<String>,
     m_image_carousel_object_ids: Vec<String>,
 }
+impl Game {
+    pub fn name(&self) -> &str {
+        &self.m_name
+    }
+}
 #[derive(serde::Serialize, Clone)]
 pub struct GameUpdateEvent {
     pub game_id: String,
@@ -65,6 +70,8 @@ pub struct QueueUpdateEventQueueData {
     pub progress: f64,
     pub current: usize,
     pub max: usize,
+    // Skipped over until it's resumed
+    pub paused: bool,
 }
 
 #[derive(serde::Serialize, Clone)]
@@ -415,6 +422,10 @@ pub fn uninstall_game_logic(meta: DownloadableMetadata, app_handle: &AppHandle)
                     .applications
                     .game_statuses
                     .insert(meta.id.clone(), GameDownloadStatus::Remote {});
+                // Its DLC and mods were inside the install directory
+                db_handle.applications.installed_dlc.remove(&meta.id);
+                db_handle.applications.installed_mods.remove(&meta.id);
+                db_handle.applications.deployed_mod_files.remove(&meta.id);
                 let _ = db_handle.applications.transient_statuses.remove(&meta);
 
                 push_game_update(
@@ -466,6 +477,19 @@ pub fn on_game_complete(
 
     let game_version: GameVersion = response.json()?;
 
+    set_installed(meta, install_dir, game_version, app_handle);
+
+    Ok(())
+}
+
+/// Records `meta` as installed at `install_dir`, needing setup first
+/// if the version has a setup command
+pub fn set_installed(
+    meta: &DownloadableMetadata,
+    install_dir: String,
+    game_version: GameVersion,
+    app_handle: &AppHandle,
+) {
     let mut handle = borrow_db_mut_checked();
     handle
         .applications
@@ -509,8 +533,15 @@ pub fn on_game_complete(
             },
         )
         .unwrap();
+}
 
-    Ok(())
+/// Makes a game brought in without the server show up in the offline library
+pub fn cache_game_metadata(game: &Game) -> Result<(), RemoteAccessError> {
+    let mut library: Vec<Game> = get_cached_object("library").unwrap_or_default();
+    library.retain(|cached| cached.id != game.id);
+    library.push(game.clone());
+    cache_object("library", &library)?;
+    cache_object(&game.id, game)
 }
 
 pub fn push_game_update(
@@ -521,9 +552,10 @@ pub fn push_game_update(
 ) {
     if let Some(GameDownloadStatus::Installed { .. } | GameDownloadStatus::SetupRequired { .. }) =
         &status.0
-        && version.is_none() {
-            panic!("pushed game for installed game that doesn't have version information");
-        }
+        && version.is_none()
+    {
+        panic!("pushed game for installed game that doesn't have version information");
+    }
 
     app_handle
         .emit(
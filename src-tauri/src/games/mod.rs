@@ -1,5 +1,8 @@
 pub mod collections;
 pub mod commands;
+pub mod dlc;
 pub mod downloads;
 pub mod library;
+pub mod mods;
+pub mod package;
 pub mod state;
@@ -0,0 +1,348 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{copy, create_dir_all, remove_dir_all, remove_file, rename},
+    io,
+    path::{Component, Path, PathBuf},
+    sync::Mutex,
+};
+
+use log::{debug, info, warn};
+use serde::Serialize;
+use tauri::{AppHandle, Emitter};
+use walkdir::WalkDir;
+
+use crate::{
+    database::{
+        db::{borrow_db_checked, borrow_db_mut_checked},
+        models::data::{Database, DeployedModFile, InstalledMod},
+    },
+    error::library_error::LibraryError,
+    games::{
+        dlc::parent_install_dir,
+        downloads::{
+            delta::DELTA_STAGING_DIR,
+            drop_data::{
+                DROP_DATA_PATH, DROP_DATA_PROGRESS_PATH, DROP_DATA_PROGRESS_TEMP_PATH,
+                DROP_DATA_TEMP_PATH,
+            },
+        },
+    },
+};
+
+/// Mods download into here, inside the game's install directory,
+/// and get copied over the game's files when they're enabled
+pub static MOD_DIR: &str = ".dropmods";
+static MOD_STORE_DIR: &str = "store";
+static MOD_BACKUP_DIR: &str = "backup";
+
+// Deployments all touch the same files, so only one runs at a time
+static DEPLOY_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ModUpdateEvent {
+    pub game_id: String,
+    pub mods: Vec<InstalledMod>,
+}
+
+pub fn mod_store_dir(parent_install_dir: &Path, mod_id: &str) -> PathBuf {
+    parent_install_dir
+        .join(MOD_DIR)
+        .join(MOD_STORE_DIR)
+        .join(mod_id)
+}
+
+/// Where the game's own files go while a mod replaces them
+pub fn mod_backup_dir(parent_install_dir: &Path) -> PathBuf {
+    parent_install_dir.join(MOD_DIR).join(MOD_BACKUP_DIR)
+}
+
+/// In load order, later mods win when they share a file
+pub fn installed_mods(db: &Database, game_id: &str) -> Vec<InstalledMod> {
+    db.applications
+        .installed_mods
+        .get(game_id)
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// Files in the game's install directory that don't belong to the game,
+/// relative to it. Validation leaves these alone.
+pub fn mod_owned_files(db: &Database, game_id: &str) -> HashSet<PathBuf> {
+    db.applications
+        .deployed_mod_files
+        .get(game_id)
+        .map(|files| files.keys().cloned().collect())
+        .unwrap_or_default()
+}
+
+pub fn push_mod_update(app_handle: &AppHandle, db: &Database, game_id: &str) {
+    app_handle
+        .emit(
+            &format!("update_mods/{game_id}"),
+            ModUpdateEvent {
+                game_id: game_id.to_owned(),
+                mods: installed_mods(db, game_id),
+            },
+        )
+        .unwrap();
+}
+
+// Download bookkeeping that lives alongside the mod's files
+fn is_download_data(relative_path: &Path) -> bool {
+    matches!(
+        relative_path.components().next(),
+        Some(Component::Normal(name)) if name == DROP_DATA_PATH
+                || name == DROP_DATA_TEMP_PATH
+                || name == DROP_DATA_PROGRESS_PATH
+                || name == DROP_DATA_PROGRESS_TEMP_PATH
+                || name == DELTA_STAGING_DIR
+    )
+}
+
+/// Puts back every file the game's mods replaced, and removes the ones they added
+fn undeploy(game_id: &str, install_dir: &Path) -> io::Result<()> {
+    let mut deployed = borrow_db_checked()
+        .applications
+        .deployed_mod_files
+        .get(game_id)
+        .cloned()
+        .unwrap_or_default();
+    let backup_dir = mod_backup_dir(install_dir);
+
+    let mut result = Ok(());
+    for (relative_path, file) in deployed.clone() {
+        let target = install_dir.join(&relative_path);
+        let restored = match remove_file(&target) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ if file.backed_up => rename(backup_dir.join(&relative_path), &target),
+            _ => Ok(()),
+        };
+        match restored {
+            Ok(()) => {
+                deployed.remove(&relative_path);
+            }
+            Err(e) => {
+                warn!("failed to restore {}: {e}", target.display());
+                result = Err(e);
+            }
+        }
+    }
+
+    // Anything we couldn't restore stays tracked, so we can try again later
+    borrow_db_mut_checked()
+        .applications
+        .deployed_mod_files
+        .insert(game_id.to_owned(), deployed);
+
+    result
+}
+
+/// Copies every enabled mod over the game's files in load order,
+/// moving any original aside the first time it gets replaced
+fn deploy(game_id: &str, install_dir: &Path) -> io::Result<()> {
+    let mods = installed_mods(&borrow_db_checked(), game_id);
+    let backup_dir = mod_backup_dir(install_dir);
+
+    let mut deployed: HashMap<PathBuf, DeployedModFile> = HashMap::new();
+    let deploy_mod = |installed_mod: &InstalledMod| -> io::Result<()> {
+        let store_dir = mod_store_dir(install_dir, &installed_mod.mod_id);
+        for entry in WalkDir::new(&store_dir).min_depth(1) {
+            let entry = entry?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let relative_path = entry.path().strip_prefix(&store_dir).unwrap();
+            if is_download_data(relative_path) {
+                continue;
+            }
+
+            let target = install_dir.join(relative_path);
+            match deployed.get_mut(relative_path) {
+                // An earlier mod already backed up whatever was here
+                Some(file) => file.mod_id = installed_mod.mod_id.clone(),
+                None => {
+                    let backed_up = target.exists();
+                    if backed_up {
+                        let backup = backup_dir.join(relative_path);
+                        create_dir_all(backup.parent().unwrap())?;
+                        rename(&target, &backup)?;
+                    }
+                    deployed.insert(
+                        relative_path.to_path_buf(),
+                        DeployedModFile {
+                            mod_id: installed_mod.mod_id.clone(),
+                            backed_up,
+                        },
+                    );
+                }
+            }
+
+            create_dir_all(target.parent().unwrap())?;
+            copy(entry.path(), &target)?;
+        }
+        Ok(())
+    };
+
+    let result = mods
+        .iter()
+        .filter(|installed_mod| installed_mod.enabled)
+        .try_for_each(deploy_mod);
+
+    // Saved even if we failed partway, so what did get deployed can be undone
+    borrow_db_mut_checked()
+        .applications
+        .deployed_mod_files
+        .insert(game_id.to_owned(), deployed);
+
+    result
+}
+
+/// Restores the game's own files, e.g. before updating it.
+/// Call `redeploy_mods` to put the mods back.
+pub fn undeploy_mods(game_id: &str) -> Result<(), LibraryError> {
+    let _deploy_lock = DEPLOY_LOCK.lock().unwrap();
+    let Some(install_dir) = parent_install_dir(&borrow_db_checked(), game_id) else {
+        return Ok(());
+    };
+
+    undeploy(game_id, &install_dir).map_err(LibraryError::ModDeployFailed)
+}
+
+/// Brings the game's install directory in line with its enabled mods and load order
+pub fn redeploy_mods(game_id: &str) -> Result<(), LibraryError> {
+    let _deploy_lock = DEPLOY_LOCK.lock().unwrap();
+    let install_dir = parent_install_dir(&borrow_db_checked(), game_id)
+        .ok_or_else(|| LibraryError::MetaNotFound(game_id.to_owned()))?;
+
+    undeploy(game_id, &install_dir).map_err(LibraryError::ModDeployFailed)?;
+    deploy(game_id, &install_dir).map_err(LibraryError::ModDeployFailed)?;
+
+    debug!("deployed mods for {game_id}");
+    Ok(())
+}
+
+/// Newly installed mods start enabled at the end of the load order,
+/// updated ones keep their place and setting
+pub fn set_mod_installed(
+    game_id: &str,
+    mod_id: &str,
+    version_name: String,
+    app_handle: &AppHandle,
+) {
+    {
+        let mut db_lock = borrow_db_mut_checked();
+        let mods = db_lock
+            .applications
+            .installed_mods
+            .entry(game_id.to_owned())
+            .or_default();
+        match mods.iter_mut().find(|installed| installed.mod_id == mod_id) {
+            Some(installed) => installed.version_name = version_name,
+            None => mods.push(InstalledMod {
+                mod_id: mod_id.to_owned(),
+                version_name,
+                enabled: true,
+            }),
+        }
+    }
+
+    if let Err(e) = redeploy_mods(game_id) {
+        warn!("failed to deploy mods for {game_id}: {e}");
+    }
+    push_mod_update(app_handle, &borrow_db_checked(), game_id);
+}
+
+pub fn set_mod_enabled_logic(
+    game_id: String,
+    mod_id: String,
+    enabled: bool,
+    app_handle: &AppHandle,
+) -> Result<(), LibraryError> {
+    {
+        let mut db_lock = borrow_db_mut_checked();
+        let installed = db_lock
+            .applications
+            .installed_mods
+            .get_mut(&game_id)
+            .and_then(|mods| mods.iter_mut().find(|installed| installed.mod_id == mod_id))
+            .ok_or(LibraryError::ModNotFound(mod_id))?;
+        installed.enabled = enabled;
+    }
+
+    let result = redeploy_mods(&game_id);
+    push_mod_update(app_handle, &borrow_db_checked(), &game_id);
+    result
+}
+
+/// Mods left out of `mod_ids` keep their relative order, after the listed ones
+pub fn set_mod_load_order_logic(
+    game_id: String,
+    mod_ids: Vec<String>,
+    app_handle: &AppHandle,
+) -> Result<(), LibraryError> {
+    {
+        let mut db_lock = borrow_db_mut_checked();
+        let mods = db_lock
+            .applications
+            .installed_mods
+            .entry(game_id.clone())
+            .or_default();
+        if let Some(unknown) = mod_ids
+            .iter()
+            .find(|mod_id| !mods.iter().any(|installed| &installed.mod_id == *mod_id))
+        {
+            return Err(LibraryError::ModNotFound(unknown.clone()));
+        }
+
+        mods.sort_by_key(|installed| {
+            mod_ids
+                .iter()
+                .position(|mod_id| *mod_id == installed.mod_id)
+                .unwrap_or(mod_ids.len())
+        });
+    }
+
+    let result = redeploy_mods(&game_id);
+    push_mod_update(app_handle, &borrow_db_checked(), &game_id);
+    result
+}
+
+pub fn uninstall_mod_logic(
+    game_id: String,
+    mod_id: String,
+    app_handle: &AppHandle,
+) -> Result<(), LibraryError> {
+    let install_dir = {
+        let mut db_lock = borrow_db_mut_checked();
+        let mods = db_lock
+            .applications
+            .installed_mods
+            .get_mut(&game_id)
+            .ok_or_else(|| LibraryError::ModNotFound(mod_id.clone()))?;
+        let index = mods
+            .iter()
+            .position(|installed| installed.mod_id == mod_id)
+            .ok_or_else(|| LibraryError::ModNotFound(mod_id.clone()))?;
+        mods.remove(index);
+        parent_install_dir(&db_lock, &game_id)
+    };
+
+    // Takes its files back out of the game before we delete them
+    let result = redeploy_mods(&game_id);
+    if let Some(install_dir) = install_dir {
+        let store_dir = mod_store_dir(&install_dir, &mod_id);
+        if store_dir.exists()
+            && let Err(e) = remove_dir_all(&store_dir)
+        {
+            warn!(
+                "failed to remove mod {mod_id} at {}: {e}",
+                store_dir.display()
+            );
+        }
+    }
+    push_mod_update(app_handle, &borrow_db_checked(), &game_id);
+
+    info!("uninstalled mod {mod_id} of {game_id}");
+    result
+}
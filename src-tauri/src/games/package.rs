@@ -0,0 +1,351 @@
+use std::{
+    collections::HashMap,
+    fs::{File, create_dir_all, remove_dir_all},
+    io::{self, Read},
+    path::{Component, Path, PathBuf},
+};
+
+use log::{info, warn};
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter};
+use walkdir::WalkDir;
+
+use crate::{
+    database::{
+        db::borrow_db_checked,
+        models::data::{
+            DeployedModFile, DownloadType, DownloadableMetadata, GameDownloadStatus, GameVersion,
+        },
+    },
+    error::package_error::PackageError,
+    games::{
+        downloads::{
+            drop_data::DropData,
+            manifest::{DropManifest, cache_manifest, get_manifest, validate_contexts},
+            validate::chunk_matches,
+        },
+        library::{Game, cache_game_metadata, set_installed},
+        mods::{MOD_DIR, mod_backup_dir},
+    },
+    remote::cache::get_cached_object,
+};
+
+/// Bumped whenever the layout of a package changes
+const PACKAGE_FORMAT: usize = 1;
+/// Always the first entry, so importing can check it before unpacking anything
+const METADATA_ENTRY: &str = "package.json";
+/// Everything from the install directory goes under here
+const FILES_ENTRY: &str = "files";
+
+/// Enough to install the game without ever talking to the server
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PackageMetadata {
+    format: usize,
+    game_id: String,
+    version_name: String,
+    game_version: GameVersion,
+    manifest: DropManifest,
+    game: Option<Game>,
+}
+
+pub async fn export_game_logic(game_id: String, destination: PathBuf) -> Result<(), PackageError> {
+    let (version_name, install_dir, game_version, deployed) = {
+        let db_lock = borrow_db_checked();
+        let (version_name, install_dir) = match db_lock.applications.game_statuses.get(&game_id) {
+            Some(
+                GameDownloadStatus::Installed {
+                    version_name,
+                    install_dir,
+                }
+                | GameDownloadStatus::SetupRequired {
+                    version_name,
+                    install_dir,
+                },
+            ) => (version_name.clone(), install_dir.clone()),
+            _ => return Err(PackageError::NotInstalled(game_id)),
+        };
+        let game_version = db_lock
+            .applications
+            .game_versions
+            .get(&game_id)
+            .and_then(|versions| versions.get(&version_name))
+            .cloned()
+            .ok_or_else(|| PackageError::MissingVersion(game_id.clone()))?;
+        let deployed = db_lock
+            .applications
+            .deployed_mod_files
+            .get(&game_id)
+            .cloned()
+            .unwrap_or_default();
+        (version_name, install_dir, game_version, deployed)
+    };
+
+    let manifest = get_manifest(&game_id, &version_name)
+        .await
+        .map_err(PackageError::Manifest)?;
+
+    let metadata = PackageMetadata {
+        format: PACKAGE_FORMAT,
+        game: get_cached_object::<Game>(&game_id).ok(),
+        game_id,
+        version_name,
+        game_version,
+        manifest,
+    };
+
+    tauri::async_runtime::spawn_blocking(move || {
+        write_package(&metadata, Path::new(&install_dir), &deployed, &destination)
+    })
+    .await
+    .map_err(|e| PackageError::InvalidPackage(e.to_string()))?
+}
+
+fn write_package(
+    metadata: &PackageMetadata,
+    install_dir: &Path,
+    deployed: &HashMap<PathBuf, DeployedModFile>,
+    destination: &Path,
+) -> Result<(), PackageError> {
+    info!(
+        "exporting {} ({}) to {}",
+        metadata.game_id,
+        metadata.version_name,
+        destination.display()
+    );
+
+    let raw_metadata =
+        serde_json::to_vec(metadata).map_err(|e| PackageError::InvalidPackage(e.to_string()))?;
+
+    let file = File::create(destination)?;
+    // Game files are mostly already compressed, so don't squeeze hard
+    let encoder = zstd::Encoder::new(file, 3)?;
+    let mut tarball = tar::Builder::new(encoder);
+
+    let mut header = tar::Header::new_gnu();
+    header.set_size(raw_metadata.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    tarball.append_data(&mut header, METADATA_ENTRY, raw_metadata.as_slice())?;
+
+    append_game_files(&mut tarball, install_dir, deployed)?;
+    tarball.into_inner()?.finish()?;
+
+    info!("finished exporting {}", metadata.game_id);
+    Ok(())
+}
+
+pub async fn import_game_logic(
+    package: PathBuf,
+    install_dir_index: usize,
+    app_handle: AppHandle,
+) -> Result<(), PackageError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        read_package(&package, install_dir_index, &app_handle)
+    })
+    .await
+    .map_err(|e| PackageError::InvalidPackage(e.to_string()))?
+}
+
+fn read_package(
+    package: &Path,
+    install_dir_index: usize,
+    app_handle: &AppHandle,
+) -> Result<(), PackageError> {
+    let base_dir = borrow_db_checked()
+        .applications
+        .install_dirs
+        .get(install_dir_index)
+        .cloned()
+        .ok_or(PackageError::InvalidInstallDir(install_dir_index))?;
+
+    let decoder = zstd::Decoder::new(File::open(package)?)?;
+    let mut archive = tar::Archive::new(decoder);
+    let mut entries = archive.entries()?;
+
+    let metadata = match entries.next() {
+        Some(entry) => {
+            let mut entry = entry?;
+            if entry.path()?.as_ref() != Path::new(METADATA_ENTRY) {
+                return Err(PackageError::InvalidPackage(format!(
+                    "expected {METADATA_ENTRY} first"
+                )));
+            }
+            let mut raw_metadata = Vec::new();
+            entry.read_to_end(&mut raw_metadata)?;
+            serde_json::from_slice::<PackageMetadata>(&raw_metadata)
+                .map_err(|e| PackageError::InvalidPackage(e.to_string()))?
+        }
+        None => return Err(PackageError::InvalidPackage("package is empty".to_owned())),
+    };
+    if metadata.format != PACKAGE_FORMAT {
+        return Err(PackageError::InvalidPackage(format!(
+            "unsupported package format {}",
+            metadata.format
+        )));
+    }
+
+    let already_installed = !matches!(
+        borrow_db_checked()
+            .applications
+            .game_statuses
+            .get(&metadata.game_id),
+        None | Some(GameDownloadStatus::Remote {})
+    );
+    if already_installed {
+        return Err(PackageError::AlreadyInstalled(metadata.game_id));
+    }
+
+    // Cleaning up after a failed import deletes the whole directory,
+    // so it has to be one we made
+    let install_dir = base_dir.join(&metadata.game_id);
+    if install_dir.exists() {
+        return Err(PackageError::TargetExists(install_dir));
+    }
+    info!(
+        "importing {} ({}) into {}",
+        metadata.game_id,
+        metadata.version_name,
+        install_dir.display()
+    );
+
+    let unpacked = (|| {
+        create_dir_all(&install_dir)?;
+        for entry in entries {
+            let mut entry = entry?;
+            let path = entry.path()?.into_owned();
+            let Some(relative) = package_relative_path(&path) else {
+                return Err(PackageError::InvalidPackage(format!(
+                    "unexpected entry {}",
+                    path.display()
+                )));
+            };
+            // Links could point anywhere, and anything written through them with it
+            let entry_type = entry.header().entry_type();
+            if !entry_type.is_file() && !entry_type.is_dir() {
+                return Err(PackageError::InvalidPackage(format!(
+                    "unsupported entry type for {}",
+                    path.display()
+                )));
+            }
+            let target = install_dir.join(relative);
+            if let Some(parent) = target.parent() {
+                create_dir_all(parent)?;
+            }
+            entry.unpack(&target)?;
+        }
+        finish_import(&metadata, &install_dir)
+    })();
+
+    if let Err(e) = unpacked {
+        warn!("failed to import {}, cleaning up: {e}", metadata.game_id);
+        let _ = remove_dir_all(&install_dir);
+        return Err(e);
+    }
+
+    if let Err(e) = cache_manifest(
+        &metadata.game_id,
+        &metadata.version_name,
+        &metadata.manifest,
+    ) {
+        warn!("failed to cache manifest for {}: {e}", metadata.game_id);
+    }
+    if let Some(game) = &metadata.game
+        && let Err(e) = cache_game_metadata(game)
+    {
+        warn!("failed to cache metadata for {}: {e}", metadata.game_id);
+    }
+
+    let meta = DownloadableMetadata::new(
+        metadata.game_id.clone(),
+        Some(metadata.version_name.clone()),
+        DownloadType::Game,
+    );
+    set_installed(
+        &meta,
+        install_dir.to_string_lossy().to_string(),
+        metadata.game_version,
+        app_handle,
+    );
+    app_handle.emit("update_library", ()).unwrap();
+
+    info!("imported {}", metadata.game_id);
+    Ok(())
+}
+
+/// Packages the game as it was installed, so it still matches the manifest:
+/// mods and their files are left out, and anything they replaced is taken
+/// from the backups
+fn append_game_files<W: io::Write>(
+    tarball: &mut tar::Builder<W>,
+    install_dir: &Path,
+    deployed: &HashMap<PathBuf, DeployedModFile>,
+) -> Result<(), PackageError> {
+    let backup_dir = mod_backup_dir(install_dir);
+    tarball.append_dir(FILES_ENTRY, install_dir)?;
+
+    let entries = WalkDir::new(install_dir)
+        .min_depth(1)
+        .into_iter()
+        .filter_entry(|entry| entry.path() != install_dir.join(MOD_DIR));
+    for entry in entries {
+        let entry = entry.map_err(io::Error::from)?;
+        let relative_path = entry.path().strip_prefix(install_dir).unwrap();
+        let name = Path::new(FILES_ENTRY).join(relative_path);
+
+        if entry.file_type().is_dir() {
+            tarball.append_dir(&name, entry.path())?;
+            continue;
+        }
+        match deployed.get(relative_path) {
+            Some(file) if file.backed_up => {
+                tarball.append_path_with_name(backup_dir.join(relative_path), &name)?;
+            }
+            // Added by a mod, the game doesn't have it
+            Some(_) => {}
+            None => tarball.append_path_with_name(entry.path(), &name)?,
+        }
+    }
+    Ok(())
+}
+
+/// Strips the files prefix, refusing anything that would escape the install directory
+fn package_relative_path(path: &Path) -> Option<PathBuf> {
+    let relative = path.strip_prefix(FILES_ENTRY).ok()?;
+    if relative
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        Some(relative.to_path_buf())
+    } else {
+        None
+    }
+}
+
+/// Checks everything that was unpacked against the manifest, and points
+/// the .dropdata at where the game lives now
+fn finish_import(metadata: &PackageMetadata, install_dir: &Path) -> Result<(), PackageError> {
+    let contexts = validate_contexts(&metadata.manifest, install_dir);
+    let invalid = contexts
+        .par_iter()
+        .filter(|context| !chunk_matches(context).unwrap_or(false))
+        .count();
+    if invalid != 0 {
+        return Err(PackageError::ValidationFailed(invalid));
+    }
+
+    let drop_data = DropData::new(
+        metadata.game_id.clone(),
+        metadata.version_name.clone(),
+        install_dir.to_path_buf(),
+    );
+    let contexts = contexts
+        .into_iter()
+        .map(|context| (context.checksum, true))
+        .collect::<Vec<(String, bool)>>();
+    drop_data.set_contexts(&contexts);
+    drop_data.write();
+
+    Ok(())
+}
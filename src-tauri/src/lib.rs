@@ -11,10 +11,14 @@ mod games;
 mod client;
 mod download_manager;
 mod error;
+mod peer;
 mod process;
 mod remote;
 
 use crate::database::scan::scan_install_dirs;
+use crate::peer::chunk_server::start_chunk_server;
+use crate::peer::commands::{fetch_discovered_peers, fetch_discovered_servers};
+use crate::peer::discovery::start_discovery;
 use crate::process::commands::open_process_logs;
 use crate::process::process_handlers::UMU_LAUNCHER_EXECUTABLE;
 use crate::remote::commands::auth_initiate_code;
@@ -32,7 +36,8 @@ use database::commands::{
 use database::db::{DATA_ROOT_DIR, DatabaseInterface, borrow_db_checked, borrow_db_mut_checked};
 use database::models::data::GameDownloadStatus;
 use download_manager::commands::{
-    cancel_game, move_download_in_queue, pause_downloads, resume_downloads,
+    cancel_game, download_next, move_download_in_queue, pause_downloads, pause_queued_download,
+    resume_downloads, resume_queued_download,
 };
 use download_manager::download_manager_builder::DownloadManagerBuilder;
 use download_manager::download_manager_frontend::DownloadManager;
@@ -41,9 +46,15 @@ use games::collections::commands::{
     fetch_collection, fetch_collections,
 };
 use games::commands::{
-    fetch_game, fetch_game_status, fetch_game_version_options, fetch_library, uninstall_game,
+    export_game, fetch_game, fetch_game_status, fetch_game_version_options, fetch_installed_dlc,
+    fetch_installed_mods, fetch_library, import_game, set_dlc_enabled, set_mod_enabled,
+    set_mod_load_order, uninstall_dlc, uninstall_game, uninstall_mod,
 };
-use games::downloads::commands::download_game;
+use games::downloads::commands::{
+    delete_stale_files, download_dlc, download_game, download_mod, download_tool,
+    fetch_stale_files, move_game, plan_download, repair_game, update_game,
+};
+use games::downloads::restore::restore_download_queue;
 use games::library::{Game, update_game_configuration};
 use log::{LevelFilter, debug, info, warn};
 use log4rs::Config;
@@ -51,7 +62,7 @@ use log4rs::append::console::ConsoleAppender;
 use log4rs::append::file::FileAppender;
 use log4rs::config::{Appender, Root};
 use log4rs::encode::pattern::PatternEncoder;
-use process::commands::{kill_game, launch_game};
+use process::commands::{fetch_installed_tools, kill_game, launch_game, uninstall_tool};
 use process::process_manager::ProcessManager;
 use remote::auth::{self, recieve_handshake};
 use remote::commands::{
@@ -171,6 +182,9 @@ async fn setup(handle: AppHandle) -> AppState<'static> {
 
     scan_install_dirs();
 
+    // Before anything else, so the setup screen can list servers
+    start_discovery();
+
     if !is_set_up {
         return AppState {
             status: AppStatus::NotConfigured,
@@ -230,6 +244,10 @@ async fn setup(handle: AppHandle) -> AppState<'static> {
 
     drop(db_handle);
 
+    start_chunk_server();
+
+    tauri::async_runtime::spawn(restore_download_queue(download_manager.clone()));
+
     debug!("finished setup!");
 
     // Sync autostart state
@@ -275,7 +293,8 @@ pub fn run() {
     let mut builder = tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_os::init())
-        .plugin(tauri_plugin_dialog::init());
+        .plugin(tauri_plugin_dialog::init())
+        .plugin(tauri_plugin_notification::init());
 
     #[cfg(desktop)]
     #[allow(unused_variables)]
@@ -305,6 +324,9 @@ pub fn run() {
             use_remote,
             gen_drop_url,
             fetch_drop_object,
+            // Local network
+            fetch_discovered_servers,
+            fetch_discovered_peers,
             // Library
             fetch_library,
             fetch_game,
@@ -323,15 +345,38 @@ pub fn run() {
             delete_game_in_collection,
             // Downloads
             download_game,
+            plan_download,
+            update_game,
+            repair_game,
+            fetch_stale_files,
+            delete_stale_files,
+            move_game,
+            download_dlc,
+            download_mod,
+            download_tool,
             resume_download,
             move_download_in_queue,
             pause_downloads,
+            pause_queued_download,
+            resume_queued_download,
+            download_next,
             resume_downloads,
             cancel_game,
             uninstall_game,
+            fetch_installed_dlc,
+            set_dlc_enabled,
+            uninstall_dlc,
+            fetch_installed_mods,
+            set_mod_enabled,
+            set_mod_load_order,
+            uninstall_mod,
+            export_game,
+            import_game,
             // Processes
             launch_game,
             kill_game,
+            fetch_installed_tools,
+            uninstall_tool,
             toggle_autostart,
             get_autostart_enabled,
             open_process_logs
@@ -0,0 +1,235 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    sync::{
+        Arc, LazyLock, Mutex, RwLock,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::spawn,
+    time::{Duration, Instant},
+};
+
+use log::{debug, info, warn};
+use tiny_http::{Method, Request, Response, Server};
+
+use crate::{
+    database::{db::borrow_db_checked, models::data::GameDownloadStatus},
+    games::downloads::{
+        hasher::ChunkHasher,
+        manifest::{ChunkLocation, chunk_index, get_cached_manifest},
+    },
+};
+
+/// Lookups that miss rebuild the index at most this often,
+/// to pick up games installed since
+const INDEX_REBUILD_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Requests are handled by this many threads, and queue up behind them
+const WORKER_COUNT: usize = 4;
+
+static SERVER: Mutex<Option<RunningServer>> = Mutex::new(None);
+
+struct RunningServer {
+    server: Arc<Server>,
+    port: u16,
+    stopped: Arc<AtomicBool>,
+}
+
+static CHUNK_INDEX: LazyLock<RwLock<ChunkIndex>> = LazyLock::new(|| {
+    RwLock::new(ChunkIndex {
+        chunks: HashMap::new(),
+        built: None,
+    })
+});
+
+struct ChunkIndex {
+    chunks: HashMap<String, ChunkLocation>,
+    built: Option<Instant>,
+}
+
+/// Starts or stops serving chunks of installed games to match the
+/// settings. Call it every time they change.
+pub fn start_chunk_server() {
+    let (enabled, port) = {
+        let db_lock = borrow_db_checked();
+        (
+            db_lock.settings.share_with_peers,
+            db_lock.settings.peer_port,
+        )
+    };
+
+    let mut running = SERVER.lock().unwrap();
+    if let Some(current) = running.as_ref() {
+        if enabled && current.port == port {
+            return;
+        }
+        info!("stopping peer chunk server on port {}", current.port);
+        current.stopped.store(true, Ordering::Release);
+        for _ in 0..WORKER_COUNT {
+            current.server.unblock();
+        }
+        *running = None;
+    }
+    if !enabled {
+        return;
+    }
+
+    let server = match Server::http(("0.0.0.0", port)) {
+        Ok(server) => Arc::new(server),
+        Err(e) => {
+            warn!("failed to start peer chunk server on port {port}: {e}");
+            return;
+        }
+    };
+
+    info!("serving chunks to peers on port {port}");
+    let stopped = Arc::new(AtomicBool::new(false));
+    for _ in 0..WORKER_COUNT {
+        let server = server.clone();
+        let stopped = stopped.clone();
+        spawn(move || {
+            loop {
+                match server.recv() {
+                    Ok(request) => handle_request(request),
+                    // Unblocked, because we're shutting down
+                    Err(_) if stopped.load(Ordering::Acquire) => return,
+                    Err(e) => debug!("peer chunk server failed to accept a request: {e}"),
+                }
+            }
+        });
+    }
+    *running = Some(RunningServer {
+        server,
+        port,
+        stopped,
+    });
+}
+
+// Chunks are only for peers on the same network, never the internet
+fn on_local_network(addr: &SocketAddr) -> bool {
+    match addr.ip() {
+        IpAddr::V4(ip) => ip.is_private() || ip.is_link_local() || ip.is_loopback(),
+        IpAddr::V6(ip) => ip.is_unique_local() || ip.is_unicast_link_local() || ip.is_loopback(),
+    }
+}
+
+fn handle_request(request: Request) {
+    if !request.remote_addr().is_some_and(on_local_network) {
+        let _ = request.respond(Response::empty(403));
+        return;
+    }
+
+    let checksum = match (request.method(), request.url().strip_prefix("/chunk/")) {
+        (Method::Get, Some(checksum)) => checksum.to_owned(),
+        _ => {
+            let _ = request.respond(Response::empty(404));
+            return;
+        }
+    };
+
+    let Some(location) = find_chunk(&checksum) else {
+        let _ = request.respond(Response::empty(404));
+        return;
+    };
+
+    match read_verified_chunk(&checksum, &location) {
+        Ok(Some(chunk)) => {
+            debug!("serving chunk {checksum} to {:?}", request.remote_addr());
+            let _ = request.respond(Response::from_data(chunk));
+        }
+        Ok(None) => {
+            warn!(
+                "not serving chunk {checksum}, the copy in {} doesn't match it",
+                location.path.display()
+            );
+            let _ = request.respond(Response::empty(404));
+        }
+        Err(e) => {
+            warn!(
+                "failed to read chunk {checksum} from {}: {e}",
+                location.path.display()
+            );
+            let _ = request.respond(Response::empty(404));
+        }
+    }
+}
+
+/// Installed files can be modified or damaged, so chunks are
+/// checked before they're sent anywhere
+fn read_verified_chunk(checksum: &str, location: &ChunkLocation) -> io::Result<Option<Vec<u8>>> {
+    let mut file = File::open(&location.path)?;
+    file.seek(SeekFrom::Start(location.offset as u64))?;
+
+    let mut chunk = Vec::with_capacity(location.length);
+    file.take(location.length as u64).read_to_end(&mut chunk)?;
+
+    let mut hasher = ChunkHasher::new(location.hash_algorithm);
+    hasher.update(&chunk);
+    Ok((chunk.len() == location.length && hasher.finalize() == checksum).then_some(chunk))
+}
+
+fn find_chunk(checksum: &str) -> Option<ChunkLocation> {
+    {
+        let index = CHUNK_INDEX.read().unwrap();
+        if let Some(location) = index.chunks.get(checksum) {
+            return Some(location.clone());
+        }
+        if index
+            .built
+            .is_some_and(|built| built.elapsed() < INDEX_REBUILD_INTERVAL)
+        {
+            return None;
+        }
+    }
+
+    let mut index = CHUNK_INDEX.write().unwrap();
+    // Someone else might have rebuilt it while we waited
+    if !index
+        .built
+        .is_some_and(|built| built.elapsed() < INDEX_REBUILD_INTERVAL)
+    {
+        index.chunks = build_chunk_index();
+        index.built = Some(Instant::now());
+    }
+    index.chunks.get(checksum).cloned()
+}
+
+/// Only games with a cached manifest can be shared, which is every
+/// game installed or updated since manifests started being cached
+fn build_chunk_index() -> HashMap<String, ChunkLocation> {
+    let installed = {
+        let db_lock = borrow_db_checked();
+        db_lock
+            .applications
+            .game_statuses
+            .iter()
+            .filter_map(|(game_id, status)| match status {
+                GameDownloadStatus::Installed {
+                    version_name,
+                    install_dir,
+                }
+                | GameDownloadStatus::SetupRequired {
+                    version_name,
+                    install_dir,
+                } => Some((
+                    game_id.clone(),
+                    version_name.clone(),
+                    PathBuf::from(install_dir),
+                )),
+                _ => None,
+            })
+            .collect::<Vec<(String, String, PathBuf)>>()
+    };
+
+    let mut index = HashMap::new();
+    for (game_id, version, install_dir) in installed {
+        match get_cached_manifest(&game_id, &version) {
+            Ok(manifest) => index.extend(chunk_index(&manifest, &install_dir)),
+            Err(e) => debug!("not sharing {game_id} ({version}) with peers: {e}"),
+        }
+    }
+    index
+}
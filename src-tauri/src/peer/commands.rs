@@ -0,0 +1,11 @@
+use super::discovery::{DiscoveredPeer, DiscoveredServer, discovered_peers, discovered_servers};
+
+#[tauri::command]
+pub fn fetch_discovered_servers() -> Vec<DiscoveredServer> {
+    discovered_servers()
+}
+
+#[tauri::command]
+pub fn fetch_discovered_peers() -> Vec<DiscoveredPeer> {
+    discovered_peers()
+}
@@ -0,0 +1,237 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{
+        LazyLock, RwLock,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::spawn,
+};
+
+use gethostname::gethostname;
+use log::{debug, info, warn};
+use mdns_sd::{Receiver, ServiceDaemon, ServiceEvent, ServiceInfo};
+use serde::Serialize;
+
+use crate::database::db::borrow_db_checked;
+
+/// Drop clients, advertising their chunk server and the server they use
+const PEER_SERVICE: &str = "_drop-peer._tcp.local.";
+/// Drop servers that announce themselves
+const SERVER_SERVICE: &str = "_drop._tcp.local.";
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DiscoveredPeer {
+    pub name: String,
+    pub address: String,
+    pub server_url: Option<String>,
+    pub sharing: bool,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DiscoveredServer {
+    pub url: String,
+    // The server itself, or the client that told us about it
+    pub name: String,
+}
+
+static DISCOVERY_STARTED: AtomicBool = AtomicBool::new(false);
+
+static DAEMON: LazyLock<Option<ServiceDaemon>> = LazyLock::new(|| match ServiceDaemon::new() {
+    Ok(daemon) => Some(daemon),
+    Err(e) => {
+        warn!("failed to start mDNS daemon, LAN discovery is disabled: {e}");
+        None
+    }
+});
+
+// Suffixed so two machines with the same hostname don't clash
+static INSTANCE_NAME: LazyLock<String> = LazyLock::new(|| {
+    let id = uuid::Uuid::new_v4().simple().to_string();
+    format!("{}-{}", gethostname().to_string_lossy(), &id[..8])
+});
+
+// Keyed by mDNS fullname, so removals can find them
+static PEERS: LazyLock<RwLock<HashMap<String, DiscoveredPeer>>> =
+    LazyLock::new(|| RwLock::new(HashMap::new()));
+static SERVERS: LazyLock<RwLock<HashMap<String, DiscoveredServer>>> =
+    LazyLock::new(|| RwLock::new(HashMap::new()));
+
+/// Starts browsing for peers and servers, and announces this client.
+/// Calling it again only refreshes the announcement.
+pub fn start_discovery() {
+    let Some(daemon) = DAEMON.as_ref() else {
+        return;
+    };
+
+    if !DISCOVERY_STARTED.swap(true, Ordering::AcqRel) {
+        for service in [PEER_SERVICE, SERVER_SERVICE] {
+            match daemon.browse(service) {
+                Ok(receiver) => {
+                    spawn(move || watch_service(service, receiver));
+                }
+                Err(e) => warn!("failed to browse for {service}: {e}"),
+            }
+        }
+        info!("browsing for Drop peers and servers on the local network");
+    }
+
+    advertise(daemon);
+}
+
+fn advertise(daemon: &ServiceDaemon) {
+    let (server_url, sharing, port) = {
+        let db_lock = borrow_db_checked();
+        (
+            db_lock.base_url.clone(),
+            db_lock.settings.share_with_peers,
+            db_lock.settings.peer_port,
+        )
+    };
+
+    // The server is announced either way, so others can find it.
+    // Nobody should find our chunk server unless we've opted in.
+    let port = if sharing { port } else { 0 };
+    let mut properties = HashMap::from([("sharing".to_owned(), sharing.to_string())]);
+    if !server_url.is_empty() {
+        properties.insert("server".to_owned(), server_url);
+    }
+
+    let host_name = format!("{}.local.", gethostname().to_string_lossy());
+    let info = match ServiceInfo::new(
+        PEER_SERVICE,
+        &INSTANCE_NAME,
+        &host_name,
+        "",
+        port,
+        properties,
+    ) {
+        Ok(info) => info.enable_addr_auto(),
+        Err(e) => {
+            warn!("failed to build mDNS announcement: {e}");
+            return;
+        }
+    };
+
+    // Registering the same name again replaces the old announcement
+    if let Err(e) = daemon.register(info) {
+        warn!("failed to announce on the local network: {e}");
+    }
+}
+
+fn watch_service(service: &'static str, receiver: Receiver<ServiceEvent>) {
+    while let Ok(event) = receiver.recv() {
+        match event {
+            ServiceEvent::ServiceResolved(info) => {
+                if service == PEER_SERVICE {
+                    peer_resolved(&info);
+                } else {
+                    server_resolved(&info);
+                }
+            }
+            ServiceEvent::ServiceRemoved(_, fullname) => {
+                debug!("{fullname} left the local network");
+                PEERS.write().unwrap().remove(&fullname);
+                SERVERS.write().unwrap().remove(&fullname);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn instance_name(info: &ServiceInfo) -> String {
+    info.get_fullname()
+        .strip_suffix(info.get_type())
+        .unwrap_or(info.get_fullname())
+        .trim_end_matches('.')
+        .to_owned()
+}
+
+/// Prefers IPv4, as that's what most LAN setups actually route
+fn socket_address(info: &ServiceInfo) -> Option<SocketAddr> {
+    let addresses = info.get_addresses();
+    let ip = addresses
+        .iter()
+        .find(|ip| ip.is_ipv4())
+        .or_else(|| addresses.iter().next())?;
+    Some(SocketAddr::new(*ip, info.get_port()))
+}
+
+fn peer_resolved(info: &ServiceInfo) {
+    let name = instance_name(info);
+    if name == *INSTANCE_NAME {
+        return;
+    }
+    let Some(address) = socket_address(info) else {
+        return;
+    };
+
+    let server_url = info.get_property_val_str("server").map(str::to_owned);
+    let peer = DiscoveredPeer {
+        name: name.clone(),
+        address: address.to_string(),
+        server_url: server_url.clone(),
+        sharing: info.get_property_val_str("sharing") == Some("true"),
+    };
+    debug!("discovered peer {peer:?}");
+    PEERS
+        .write()
+        .unwrap()
+        .insert(info.get_fullname().to_owned(), peer);
+
+    if let Some(url) = server_url {
+        SERVERS.write().unwrap().insert(
+            info.get_fullname().to_owned(),
+            DiscoveredServer { url, name },
+        );
+    }
+}
+
+fn server_resolved(info: &ServiceInfo) {
+    let url = match info.get_property_val_str("url") {
+        Some(url) => url.to_owned(),
+        None => match socket_address(info) {
+            Some(address) => format!("http://{address}/"),
+            None => return,
+        },
+    };
+    debug!("discovered server {url}");
+    SERVERS.write().unwrap().insert(
+        info.get_fullname().to_owned(),
+        DiscoveredServer {
+            url,
+            name: instance_name(info),
+        },
+    );
+}
+
+pub fn discovered_peers() -> Vec<DiscoveredPeer> {
+    PEERS.read().unwrap().values().cloned().collect()
+}
+
+/// One entry per URL, however many clients announced it
+pub fn discovered_servers() -> Vec<DiscoveredServer> {
+    let mut servers = HashMap::new();
+    for server in SERVERS.read().unwrap().values() {
+        servers
+            .entry(server.url.clone())
+            .or_insert_with(|| server.clone());
+    }
+    let mut servers = servers.into_values().collect::<Vec<DiscoveredServer>>();
+    servers.sort_by(|a, b| a.url.cmp(&b.url));
+    servers
+}
+
+/// Peers that are sharing and use the same server as us,
+/// as anyone else won't have the same games
+pub fn sharing_peers(server_url: &str) -> Vec<String> {
+    PEERS
+        .read()
+        .unwrap()
+        .values()
+        .filter(|peer| peer.sharing && peer.server_url.as_deref() == Some(server_url))
+        .map(|peer| peer.address.clone())
+        .collect()
+}
@@ -0,0 +1,61 @@
+pub mod chunk_server;
+pub mod commands;
+pub mod discovery;
+
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::database::db::borrow_db_checked;
+
+use discovery::sharing_peers;
+
+/// How long to stop asking a peer for chunks after it fails to send one
+const UNREACHABLE_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Peers speak plain HTTP on the LAN, so they get their own client
+/// rather than the one set up for the Drop server
+pub static PEER_CLIENT: LazyLock<reqwest::blocking::Client> = LazyLock::new(|| {
+    reqwest::blocking::ClientBuilder::new()
+        .connect_timeout(Duration::from_secs(1))
+        .timeout(Duration::from_secs(60))
+        .build()
+        .unwrap()
+});
+
+static UNREACHABLE_PEERS: LazyLock<Mutex<HashMap<String, Instant>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// host:port of every peer worth asking for chunks right now,
+/// both configured and discovered
+pub fn known_peers() -> Vec<String> {
+    let (mut peers, server_url) = {
+        let db_lock = borrow_db_checked();
+        (db_lock.settings.peers.clone(), db_lock.base_url.clone())
+    };
+    for peer in sharing_peers(&server_url) {
+        if !peers.contains(&peer) {
+            peers.push(peer);
+        }
+    }
+
+    let mut unreachable = UNREACHABLE_PEERS.lock().unwrap();
+    unreachable.retain(|_, since| since.elapsed() < UNREACHABLE_BACKOFF);
+    peers
+        .into_iter()
+        .filter(|peer| !unreachable.contains_key(peer))
+        .collect()
+}
+
+pub fn mark_unreachable(peer: &str) {
+    UNREACHABLE_PEERS
+        .lock()
+        .unwrap()
+        .insert(peer.to_owned(), Instant::now());
+}
+
+pub fn chunk_url(peer: &str, checksum: &str) -> String {
+    format!("http://{peer}/chunk/{checksum}")
+}
@@ -1,6 +1,13 @@
-use std::sync::Mutex;
+use std::{collections::HashMap, sync::Mutex};
 
-use crate::{error::process_error::ProcessError, AppState};
+use tauri::AppHandle;
+
+use crate::{
+    AppState,
+    database::db::borrow_db_checked,
+    error::process_error::ProcessError,
+    process::tools::{installed_tools, uninstall_tool_logic},
+};
 
 #[tauri::command]
 pub fn launch_game(
@@ -48,3 +55,17 @@ pub fn open_process_logs(
     let mut process_manager_lock = state_lock.process_manager.lock().unwrap();
     process_manager_lock.open_process_logs(game_id)
 }
+
+#[tauri::command]
+pub fn fetch_installed_tools() -> HashMap<String, Vec<String>> {
+    installed_tools(&borrow_db_checked())
+}
+
+#[tauri::command]
+pub fn uninstall_tool(
+    name: String,
+    version: String,
+    app_handle: AppHandle,
+) -> Result<(), ProcessError> {
+    uninstall_tool_logic(name, version, &app_handle)
+}
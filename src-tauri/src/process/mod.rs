@@ -1,5 +1,6 @@
 pub mod commands;
-pub mod process_manager;
-pub mod process_handlers;
 pub mod format;
-pub mod utils;
\ No newline at end of file
+pub mod process_handlers;
+pub mod process_manager;
+pub mod tools;
+pub mod utils;
@@ -10,7 +10,11 @@ use log::debug;
 use crate::{
     AppState,
     database::models::data::{Database, DownloadableMetadata, GameVersion},
-    process::process_manager::{Platform, ProcessHandler},
+    error::process_error::ProcessError,
+    process::{
+        process_manager::{Platform, ProcessHandler},
+        tools::{latest_tool, resolve_tool},
+    },
 };
 
 pub struct NativeGameLauncher;
@@ -22,8 +26,9 @@ impl ProcessHandler for NativeGameLauncher {
         args: Vec<String>,
         _game_version: &GameVersion,
         _current_dir: &str,
-    ) -> String {
-        format!("\"{}\" {}", launch_command, args.join(" "))
+        _db: &Database,
+    ) -> Result<String, ProcessError> {
+        Ok(format!("\"{}\" {}", launch_command, args.join(" ")))
     }
 
     fn valid_for_platform(&self, _db: &Database, _state: &AppState, _target: &Platform) -> bool {
@@ -38,6 +43,8 @@ pub static UMU_LAUNCHER_EXECUTABLE: LazyLock<PathBuf> = LazyLock::new(|| {
 });
 const UMU_BASE_LAUNCHER_EXECUTABLE: &str = "umu-run";
 const UMU_INSTALL_DIRS: [&str; 4] = ["/app/share", "/use/local/share", "/usr/share", "/opt"];
+// The server can distribute UMU itself as a tool
+const UMU_TOOL_NAME: &str = "umu-launcher";
 
 fn get_umu_executable() -> PathBuf {
     if check_executable_exists(UMU_BASE_LAUNCHER_EXECUTABLE) {
@@ -56,16 +63,30 @@ fn check_executable_exists<P: AsRef<OsStr>>(exec: P) -> bool {
     let has_umu_installed = Command::new(exec).stdout(Stdio::null()).spawn();
     has_umu_installed.is_ok()
 }
+/// Prefers a downloaded UMU over one installed on the system
+fn umu_executable(db: &Database) -> PathBuf {
+    match latest_tool(db, UMU_TOOL_NAME) {
+        Some(tool_dir) => tool_dir.join(UMU_BASE_LAUNCHER_EXECUTABLE),
+        None => UMU_LAUNCHER_EXECUTABLE.clone(),
+    }
+}
+fn umu_available(db: &Database, state: &AppState) -> bool {
+    if latest_tool(db, UMU_TOOL_NAME).is_some() {
+        return true;
+    }
+    let Some(ref compat_info) = state.compat_info else {
+        return false;
+    };
+    compat_info.umu_installed
+}
 pub struct UMULauncher;
-impl ProcessHandler for UMULauncher {
-    fn create_launch_process(
+impl UMULauncher {
+    /// Environment UMU needs for this game, including the runner it's pinned to
+    fn umu_environment(
         &self,
-        _meta: &DownloadableMetadata,
-        launch_command: String,
-        args: Vec<String>,
         game_version: &GameVersion,
-        _current_dir: &str,
-    ) -> String {
+        db: &Database,
+    ) -> Result<String, ProcessError> {
         debug!("Game override: \"{:?}\"", &game_version.umu_id_override);
         let game_id = match &game_version.umu_id_override {
             Some(game_override) => {
@@ -77,19 +98,37 @@ impl ProcessHandler for UMULauncher {
             }
             None => game_version.game_id.clone(),
         };
-        format!(
-            "GAMEID={game_id} {umu:?} \"{launch}\" {args}",
-            umu = &*UMU_LAUNCHER_EXECUTABLE,
+
+        let Some(compat_tool) = &game_version.compat_tool else {
+            return Ok(format!("GAMEID={game_id}"));
+        };
+        let proton_path = resolve_tool(db, compat_tool).ok_or_else(|| {
+            ProcessError::ToolNotInstalled(compat_tool.name.clone(), compat_tool.version.clone())
+        })?;
+        Ok(format!("GAMEID={game_id} PROTONPATH={proton_path:?}"))
+    }
+}
+impl ProcessHandler for UMULauncher {
+    fn create_launch_process(
+        &self,
+        _meta: &DownloadableMetadata,
+        launch_command: String,
+        args: Vec<String>,
+        game_version: &GameVersion,
+        _current_dir: &str,
+        db: &Database,
+    ) -> Result<String, ProcessError> {
+        Ok(format!(
+            "{env} {umu:?} \"{launch}\" {args}",
+            env = self.umu_environment(game_version, db)?,
+            umu = umu_executable(db),
             launch = launch_command,
             args = args.join(" ")
-        )
+        ))
     }
 
-    fn valid_for_platform(&self, _db: &Database, state: &AppState, _target: &Platform) -> bool {
-        let Some(ref compat_info) = state.compat_info else {
-            return false;
-        };
-        compat_info.umu_installed
+    fn valid_for_platform(&self, db: &Database, state: &AppState, _target: &Platform) -> bool {
+        umu_available(db, state)
     }
 }
 
@@ -97,33 +136,26 @@ pub struct AsahiMuvmLauncher;
 impl ProcessHandler for AsahiMuvmLauncher {
     fn create_launch_process(
         &self,
-        meta: &DownloadableMetadata,
+        _meta: &DownloadableMetadata,
         launch_command: String,
         args: Vec<String>,
         game_version: &GameVersion,
-        current_dir: &str,
-    ) -> String {
+        _current_dir: &str,
+        db: &Database,
+    ) -> Result<String, ProcessError> {
         let umu_launcher = UMULauncher {};
-        let umu_string = umu_launcher.create_launch_process(
-            meta,
-            launch_command,
-            args,
-            game_version,
-            current_dir,
-        );
-        let mut args_cmd = umu_string
-            .split("umu-run")
-            .collect::<Vec<&str>>()
-            .into_iter();
-        let args = args_cmd.next().unwrap().trim();
-        let cmd = format!("umu-run{}", args_cmd.next().unwrap());
-
-        format!("{args} muvm -- {cmd}")
+        Ok(format!(
+            "{env} muvm -- {umu:?} \"{launch}\" {args}",
+            env = umu_launcher.umu_environment(game_version, db)?,
+            umu = umu_executable(db),
+            launch = launch_command,
+            args = args.join(" ")
+        ))
     }
 
     #[allow(unreachable_code)]
     #[allow(unused_variables)]
-    fn valid_for_platform(&self, _db: &Database, state: &AppState, _target: &Platform) -> bool {
+    fn valid_for_platform(&self, db: &Database, state: &AppState, _target: &Platform) -> bool {
         #[cfg(not(target_os = "linux"))]
         return false;
 
@@ -135,10 +167,6 @@ impl ProcessHandler for AsahiMuvmLauncher {
             return false;
         }
 
-        let Some(ref compat_info) = state.compat_info else {
-            return false;
-        };
-
-        compat_info.umu_installed
+        umu_available(db, state)
     }
 }
@@ -1,5 +1,6 @@
 use std::{
     collections::HashMap,
+    env,
     fs::{OpenOptions, create_dir_all},
     io::{self},
     path::PathBuf,
@@ -28,7 +29,7 @@ use crate::{
         },
     },
     error::process_error::ProcessError,
-    games::{library::push_game_update, state::GameStatusManager},
+    games::{dlc::enabled_dlc_dirs, library::push_game_update, state::GameStatusManager},
     process::{
         format::DropFormatArgs,
         process_handlers::{AsahiMuvmLauncher, NativeGameLauncher, UMULauncher},
@@ -245,6 +246,12 @@ impl ProcessManager<'_> {
 
         let mut db_lock = borrow_db_mut_checked();
 
+        if let Some(ApplicationTransientStatus::Moving {}) =
+            db_lock.applications.transient_statuses.get(&meta)
+        {
+            return Err(ProcessError::Moving);
+        }
+
         let game_status = db_lock
             .applications
             .game_statuses
@@ -278,7 +285,7 @@ impl ProcessManager<'_> {
             .ok_or(ProcessError::InvalidVersion)?;
 
         // TODO: refactor this path with open_process_logs
-        let game_log_folder = &self.get_log_dir(game_id);
+        let game_log_folder = &self.get_log_dir(game_id.clone());
         create_dir_all(game_log_folder).map_err(ProcessError::IOError)?;
 
         let current_time = chrono::offset::Local::now();
@@ -331,7 +338,8 @@ impl ProcessManager<'_> {
             args.clone(),
             game_version,
             install_dir,
-        );
+            &db_lock,
+        )?;
 
         let format_args = DropFormatArgs::new(
             launch_string,
@@ -367,6 +375,14 @@ impl ProcessManager<'_> {
             .env_remove("RUST_LOG")
             .current_dir(install_dir);
 
+        // So the game can find its enabled DLC
+        let dlc_dirs = enabled_dlc_dirs(&db_lock, &game_id);
+        if !dlc_dirs.is_empty()
+            && let Ok(dlc_paths) = env::join_paths(dlc_dirs)
+        {
+            command.env("DROP_DLC_PATHS", dlc_paths);
+        }
+
         let child = command.spawn().map_err(ProcessError::IOError)?;
 
         let launch_process_handle =
@@ -468,7 +484,8 @@ pub trait ProcessHandler: Send + 'static {
         args: Vec<String>,
         game_version: &GameVersion,
         current_dir: &str,
-    ) -> String;
+        db: &Database,
+    ) -> Result<String, ProcessError>;
 
     fn valid_for_platform(&self, db: &Database, state: &AppState, target: &Platform) -> bool;
 }
@@ -0,0 +1,132 @@
+use std::{
+    collections::HashMap,
+    fs::remove_dir_all,
+    path::{Component, Path, PathBuf},
+    sync::LazyLock,
+};
+
+use log::{info, warn};
+use tauri::{AppHandle, Emitter};
+
+use crate::{
+    database::{
+        db::{DATA_ROOT_DIR, borrow_db_checked, borrow_db_mut_checked},
+        models::data::{Database, PinnedTool},
+    },
+    error::process_error::ProcessError,
+};
+
+/// Tools such as Proton builds and runtimes, one directory per version
+pub static TOOLS_DIR: LazyLock<PathBuf> = LazyLock::new(|| DATA_ROOT_DIR.join("tools"));
+
+/// None if the name or version would put it anywhere else
+pub fn tool_install_dir(name: &str, version: &str) -> Option<PathBuf> {
+    let is_plain = |part: &str| {
+        let mut components = Path::new(part).components();
+        matches!(components.next(), Some(Component::Normal(_))) && components.next().is_none()
+    };
+    (is_plain(name) && is_plain(version)).then(|| TOOLS_DIR.join(name).join(version))
+}
+
+pub fn installed_tools(db: &Database) -> HashMap<String, Vec<String>> {
+    db.applications.installed_tools.clone()
+}
+
+/// Where a pinned tool is installed, if it is
+pub fn resolve_tool(db: &Database, tool: &PinnedTool) -> Option<PathBuf> {
+    db.applications
+        .installed_tools
+        .get(&tool.name)?
+        .contains(&tool.version)
+        .then(|| tool_install_dir(&tool.name, &tool.version))?
+}
+
+/// The most recently installed version of a tool, for when nothing pins one
+pub fn latest_tool(db: &Database, name: &str) -> Option<PathBuf> {
+    let version = db.applications.installed_tools.get(name)?.last()?;
+    tool_install_dir(name, version)
+}
+
+/// Installed games whose current version is pinned to the tool
+fn games_pinning(db: &Database, name: &str, version: &str) -> Vec<String> {
+    let mut game_ids = db
+        .applications
+        .installed_game_version
+        .iter()
+        .filter(|(game_id, meta)| {
+            meta.version
+                .as_ref()
+                .and_then(|version_name| {
+                    db.applications
+                        .game_versions
+                        .get(*game_id)?
+                        .get(version_name)
+                })
+                .and_then(|game_version| game_version.compat_tool.as_ref())
+                .is_some_and(|tool| tool.name == name && tool.version == version)
+        })
+        .map(|(game_id, _)| game_id.clone())
+        .collect::<Vec<String>>();
+    game_ids.sort();
+    game_ids
+}
+
+pub fn push_tools_update(app_handle: &AppHandle, db: &Database) {
+    app_handle
+        .emit("update_tools", installed_tools(db))
+        .unwrap();
+}
+
+pub fn set_tool_installed(name: &str, version: &str, app_handle: &AppHandle) {
+    let mut db_lock = borrow_db_mut_checked();
+    let versions = db_lock
+        .applications
+        .installed_tools
+        .entry(name.to_owned())
+        .or_default();
+    versions.retain(|installed| installed != version);
+    versions.push(version.to_owned());
+    push_tools_update(app_handle, &db_lock);
+
+    info!("installed tool {name} {version}");
+}
+
+pub fn uninstall_tool_logic(
+    name: String,
+    version: String,
+    app_handle: &AppHandle,
+) -> Result<(), ProcessError> {
+    {
+        let mut db_lock = borrow_db_mut_checked();
+        // They wouldn't launch without it
+        let pinned_by = games_pinning(&db_lock, &name, &version);
+        if !pinned_by.is_empty() {
+            return Err(ProcessError::ToolInUse(name, version, pinned_by));
+        }
+
+        let versions = db_lock
+            .applications
+            .installed_tools
+            .get_mut(&name)
+            .filter(|versions| versions.contains(&version))
+            .ok_or_else(|| ProcessError::ToolNotInstalled(name.clone(), version.clone()))?;
+        versions.retain(|installed| *installed != version);
+        if versions.is_empty() {
+            db_lock.applications.installed_tools.remove(&name);
+        }
+    }
+
+    if let Some(install_dir) = tool_install_dir(&name, &version)
+        && install_dir.exists()
+        && let Err(e) = remove_dir_all(&install_dir)
+    {
+        warn!(
+            "failed to remove tool {name} {version} at {}: {e}",
+            install_dir.display()
+        );
+    }
+    push_tools_update(app_handle, &borrow_db_checked());
+
+    info!("uninstalled tool {name} {version}");
+    Ok(())
+}
@@ -1,4 +1,10 @@
-use std::{path::PathBuf, sync::Arc};
+#[cfg(unix)]
+use std::os::unix::fs::MetadataExt;
+use std::{
+    fs::{Metadata, metadata},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use futures_lite::io;
 use sysinfo::{Disk, DiskRefreshKind, Disks};
@@ -21,7 +27,29 @@ pub fn get_disk_available(mount_point: PathBuf) -> Result<u64, ApplicationDownlo
             return Ok(disk.available_space());
         }
     }
-    Err(ApplicationDownloadError::IoError(Arc::new(io::Error::other(
-        "could not find disk of path",
-    ))))
+    Err(ApplicationDownloadError::IoError(Arc::new(
+        io::Error::other("could not find disk of path"),
+    )))
+}
+
+/// How much more of the disk `path` will take up once it's at least `length`
+/// bytes long, at most the `incomplete` bytes still to be written to it.
+/// Counts the blocks actually allocated, as the length says nothing about
+/// holes in sparse files.
+pub fn space_needed(path: &Path, length: u64, incomplete: u64) -> u64 {
+    let (current_length, allocated) = metadata(path)
+        .map(|file| (file.len(), allocated_bytes(&file)))
+        .unwrap_or((0, 0));
+    incomplete.min(length.max(current_length).saturating_sub(allocated))
+}
+
+#[cfg(unix)]
+fn allocated_bytes(file: &Metadata) -> u64 {
+    // Always in 512 byte units, whatever the filesystem's block size
+    file.blocks() * 512
+}
+
+#[cfg(not(unix))]
+fn allocated_bytes(file: &Metadata) -> u64 {
+    file.len()
 }
@@ -14,6 +14,8 @@ use crate::{
     AppState, AppStatus,
     database::db::{DATA_ROOT_DIR, borrow_db_mut_checked},
     error::remote_access_error::RemoteAccessError,
+    peer::discovery::start_discovery,
+    remote::requests::generate_url,
 };
 
 #[derive(Deserialize)]
@@ -84,6 +86,21 @@ pub fn get_client_ws() -> reqwest::Client {
     client.use_rustls_tls().http1_only().build().unwrap()
 }
 
+/// Blocking. Only checks that something answering as Drop is up,
+/// not that we're signed in to it.
+pub fn server_reachable() -> bool {
+    let Ok(test_endpoint) = generate_url(&["/api/v1"], &[]) else {
+        return false;
+    };
+
+    DROP_CLIENT_SYNC
+        .get(test_endpoint)
+        .timeout(Duration::from_secs(3))
+        .send()
+        .and_then(reqwest::blocking::Response::json::<DropHealthcheck>)
+        .is_ok_and(|result| result.app_name == "Drop")
+}
+
 pub async fn use_remote_logic(
     url: String,
     state: tauri::State<'_, Mutex<AppState<'_>>>,
@@ -113,6 +130,10 @@ pub async fn use_remote_logic(
 
     let mut db_state = borrow_db_mut_checked();
     db_state.base_url = base_url.to_string();
+    drop(db_state);
+
+    // Tell peers which server we're on now
+    start_discovery();
 
     Ok(())
 }